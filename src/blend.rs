@@ -0,0 +1,521 @@
+//! Pixel math of the layer modes of GIMP.
+//!
+//! Channels are `f32` between 0.0 and 1.0, colors are not premultiplied by their alpha.
+
+use crate::data::mode::{LayerColorSpace, LayerCompositeMode, LayerMode};
+
+/// Relative luminance of a color, with the Rec. 709 coefficients GIMP uses.
+pub fn luminance(color: [f32; 3]) -> f32 {
+    0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2]
+}
+
+/// Blend the color of a layer pixel with the color of the backdrop below it, the result still
+/// has to be composited, see [`composite`].
+///
+/// The LCH modes take gamma corrected sRGB colors, whatever the blend space.
+///
+/// Modes which don't change colors (normal, dissolve, behind, erase, merge, split, color
+/// erase and pass through) return `layer`.
+pub fn blend(mode: LayerMode, backdrop: [f32; 3], layer: [f32; 3]) -> [f32; 3] {
+    use crate::data::mode::LayerMode::*;
+    let per_channel = |f: fn(f32, f32) -> f32| {
+        [
+            f(backdrop[0], layer[0]),
+            f(backdrop[1], layer[1]),
+            f(backdrop[2], layer[2]),
+        ]
+    };
+    let blended = match mode {
+        MultiplyLegacy | Multiply => per_channel(|b, l| b * l),
+        ScreenLegacy | Screen => per_channel(|b, l| 1.0 - (1.0 - b) * (1.0 - l)),
+        // GIMP 2.8 overlay was a soft light
+        OverlayLegacy => per_channel(|b, l| b * (b + 2.0 * l * (1.0 - b))),
+        Overlay => per_channel(|b, l| {
+            if b < 0.5 {
+                2.0 * b * l
+            } else {
+                1.0 - 2.0 * (1.0 - b) * (1.0 - l)
+            }
+        }),
+        DifferenceLegacy | Difference => per_channel(|b, l| (b - l).abs()),
+        AdditionLegacy | Addition => per_channel(|b, l| b + l),
+        SubtractLegacy | Subtract => per_channel(|b, l| b - l),
+        DarkenOnlyLegacy | DarkenOnly => per_channel(f32::min),
+        LightenOnlyLegacy | LightenOnly => per_channel(f32::max),
+        DivideLegacy | Divide => per_channel(divide),
+        DodgeLegacy | Dodge => per_channel(dodge),
+        BurnLegacy | Burn => per_channel(burn),
+        HardlightLegacy | Hardlight => per_channel(|b, l| {
+            if l > 0.5 {
+                1.0 - (1.0 - b) * (1.0 - 2.0 * (l - 0.5))
+            } else {
+                b * 2.0 * l
+            }
+        }),
+        SoftlightLegacy | Softlight => per_channel(|b, l| {
+            let multiply = b * l;
+            let screen = 1.0 - (1.0 - b) * (1.0 - l);
+            (1.0 - b) * multiply + b * screen
+        }),
+        GrainExtractLegacy | GrainExtract => per_channel(|b, l| b - l + 0.5),
+        GrainMergeLegacy | GrainMerge => per_channel(|b, l| b + l - 0.5),
+        VividLight => per_channel(|b, l| {
+            if l <= 0.5 {
+                burn(b, 2.0 * l)
+            } else {
+                dodge(b, 2.0 * (l - 0.5))
+            }
+        }),
+        PinLight => per_channel(|b, l| {
+            if l > 0.5 {
+                b.max(2.0 * (l - 0.5))
+            } else {
+                b.min(2.0 * l)
+            }
+        }),
+        LinearLight => per_channel(|b, l| b + 2.0 * l - 1.0),
+        HardMix => per_channel(|b, l| if b + l < 1.0 { 0.0 } else { 1.0 }),
+        Exclusion => per_channel(|b, l| 0.5 - 2.0 * (b - 0.5) * (l - 0.5)),
+        LinearBurn => per_channel(|b, l| b + l - 1.0),
+        LumaDarkenOnly => {
+            if luminance(layer) < luminance(backdrop) {
+                layer
+            } else {
+                backdrop
+            }
+        }
+        LumaLightenOnly => {
+            if luminance(layer) > luminance(backdrop) {
+                layer
+            } else {
+                backdrop
+            }
+        }
+        Luminance => {
+            let backdrop_luminance = luminance(backdrop);
+            if backdrop_luminance > 0.0 {
+                let ratio = luminance(layer) / backdrop_luminance;
+                backdrop.map(|c| c * ratio)
+            } else {
+                [luminance(layer); 3]
+            }
+        }
+        HsvHueLegacy | HsvHue => {
+            let (hue, saturation, _) = rgb_to_hsv(layer);
+            let (_, backdrop_saturation, value) = rgb_to_hsv(backdrop);
+            if saturation > 0.0 {
+                hsv_to_rgb(hue, backdrop_saturation, value)
+            } else {
+                backdrop
+            }
+        }
+        HsvSaturationLegacy | HsvSaturation => {
+            let (_, saturation, _) = rgb_to_hsv(layer);
+            let (hue, _, value) = rgb_to_hsv(backdrop);
+            hsv_to_rgb(hue, saturation, value)
+        }
+        HsvValueLegacy | HsvValue => {
+            let (_, _, value) = rgb_to_hsv(layer);
+            let (hue, saturation, _) = rgb_to_hsv(backdrop);
+            hsv_to_rgb(hue, saturation, value)
+        }
+        HslColorLegacy | HslColor => {
+            let (hue, saturation, _) = rgb_to_hsl(layer);
+            let (_, _, lightness) = rgb_to_hsl(backdrop);
+            hsl_to_rgb(hue, saturation, lightness)
+        }
+        LchHue | LchChroma | LchColor | LchLightness => {
+            let [l, c, h] = rgb_to_lch(layer);
+            let [backdrop_l, backdrop_c, backdrop_h] = rgb_to_lch(backdrop);
+            let lch = match mode {
+                // a gray layer has no hue to give
+                LchHue if c > 0.0 => [backdrop_l, backdrop_c, h],
+                LchHue => [backdrop_l, backdrop_c, backdrop_h],
+                LchChroma => [backdrop_l, c, backdrop_h],
+                LchColor => [backdrop_l, c, h],
+                _ => [l, backdrop_c, backdrop_h],
+            };
+            lch_to_rgb(lch)
+        }
+        NormalLegacy | Normal | Dissolve | BehindLegacy | Behind | ColorEraseLegacy
+        | ColorErase | Erase | Merge | Split | PassThrough => layer,
+    };
+    if mode.is_legacy() {
+        // GIMP 2.8 worked on 8-bit integers
+        blended.map(|c| c.clamp(0.0, 1.0))
+    } else {
+        blended
+    }
+}
+
+fn divide(backdrop: f32, layer: f32) -> f32 {
+    if layer > 0.0 {
+        backdrop / layer
+    } else if backdrop > 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn dodge(backdrop: f32, layer: f32) -> f32 {
+    if layer < 1.0 {
+        (backdrop / (1.0 - layer)).min(1.0)
+    } else if backdrop > 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn burn(backdrop: f32, layer: f32) -> f32 {
+    if layer > 0.0 {
+        (1.0 - (1.0 - backdrop) / layer).max(0.0)
+    } else if backdrop < 1.0 {
+        0.0
+    } else {
+        1.0
+    }
+}
+
+/// How a layer is composited onto its backdrop, see [`Layer::compositing`].
+///
+/// [`Layer::compositing`]: crate::data::layer::Layer::compositing
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Compositing {
+    pub mode: LayerMode,
+    pub blend_space: LayerColorSpace,
+    pub composite_space: LayerColorSpace,
+    pub composite_mode: LayerCompositeMode,
+}
+
+impl Compositing {
+    /// The defaults of GIMP for `mode`.
+    pub fn new(mode: LayerMode) -> Self {
+        Compositing {
+            mode,
+            blend_space: mode.blend_space(),
+            composite_space: mode.composite_space(),
+            composite_mode: mode.composite_mode(),
+        }
+    }
+
+    /// Composite a layer pixel onto a backdrop pixel, both in linear light.
+    ///
+    /// Colors are converted to the blend space to be blended and to the composite space to be
+    /// composited, the result is in linear light again. See [`composite`] for `opacity`.
+    pub fn composite(&self, backdrop: [f32; 4], layer: [f32; 4], opacity: f32) -> [f32; 4] {
+        let blended = blend(
+            self.mode,
+            rgb(encode(self.blend_space, backdrop)),
+            rgb(encode(self.blend_space, layer)),
+        );
+        let [r, g, b] = blended;
+        let blended = rgb(encode(
+            self.composite_space,
+            decode(self.blend_space, [r, g, b, 1.0]),
+        ));
+        let pixel = mix(
+            self.mode,
+            self.composite_mode,
+            encode(self.composite_space, backdrop),
+            encode(self.composite_space, layer),
+            blended,
+            opacity,
+        );
+        decode(self.composite_space, pixel)
+    }
+}
+
+fn rgb(pixel: [f32; 4]) -> [f32; 3] {
+    [pixel[0], pixel[1], pixel[2]]
+}
+
+/// Convert a pixel in linear light to `space`.
+fn encode(space: LayerColorSpace, pixel: [f32; 4]) -> [f32; 4] {
+    match space {
+        // the LCH modes of `blend` take gamma corrected colors
+        LayerColorSpace::RgbPerceptual | LayerColorSpace::Lab => {
+            let [r, g, b] = rgb(pixel).map(linear_to_srgb);
+            [r, g, b, pixel[3]]
+        }
+        LayerColorSpace::RgbLinear | LayerColorSpace::Auto => pixel,
+    }
+}
+
+/// Convert a pixel in `space` to linear light.
+fn decode(space: LayerColorSpace, pixel: [f32; 4]) -> [f32; 4] {
+    match space {
+        LayerColorSpace::RgbPerceptual | LayerColorSpace::Lab => {
+            let [r, g, b] = rgb(pixel).map(srgb_to_linear);
+            [r, g, b, pixel[3]]
+        }
+        LayerColorSpace::RgbLinear | LayerColorSpace::Auto => pixel,
+    }
+}
+
+/// Composite a layer pixel onto a backdrop pixel, in the color space they are given in.
+///
+/// `opacity` is the opacity of the layer multiplied by its mask, colors are blended with
+/// [`blend`] where both pixels are present. Dissolve is composited like normal, the caller is
+/// expected to have turned the alpha into 0 or 1 with [`dissolve_threshold`].
+///
+/// Behind, erase, merge, split and color erase have their own way to combine alphas and ignore
+/// `composite_mode`.
+pub fn composite(
+    mode: LayerMode,
+    composite_mode: LayerCompositeMode,
+    backdrop: [f32; 4],
+    layer: [f32; 4],
+    opacity: f32,
+) -> [f32; 4] {
+    let blended = blend(mode, rgb(backdrop), rgb(layer));
+    mix(mode, composite_mode, backdrop, layer, blended, opacity)
+}
+
+/// Combine `blended`, the colors of `layer` blended with `backdrop`, with both pixels.
+fn mix(
+    mode: LayerMode,
+    composite_mode: LayerCompositeMode,
+    backdrop: [f32; 4],
+    layer: [f32; 4],
+    blended: [f32; 3],
+    opacity: f32,
+) -> [f32; 4] {
+    use crate::data::mode::LayerMode::*;
+    let layer_alpha = layer[3] * opacity;
+    let backdrop_alpha = backdrop[3];
+    match mode {
+        BehindLegacy | Behind => mix(
+            Normal,
+            LayerCompositeMode::Union,
+            [layer[0], layer[1], layer[2], layer_alpha],
+            backdrop,
+            rgb(backdrop),
+            1.0,
+        ),
+        Erase => {
+            let mut pixel = backdrop;
+            pixel[3] = backdrop_alpha * (1.0 - layer_alpha);
+            pixel
+        }
+        Merge => {
+            let backdrop_alpha = backdrop_alpha.min(1.0 - layer_alpha);
+            let alpha = backdrop_alpha + layer_alpha;
+            if alpha <= 0.0 {
+                return [0.0; 4];
+            }
+            let ratio = layer_alpha / alpha;
+            let mut pixel = [0.0, 0.0, 0.0, alpha];
+            for c in 0..3 {
+                pixel[c] = backdrop[c] + (layer[c] - backdrop[c]) * ratio;
+            }
+            pixel
+        }
+        Split => {
+            let mut pixel = backdrop;
+            pixel[3] = (backdrop_alpha - layer_alpha).max(0.0);
+            pixel
+        }
+        ColorEraseLegacy | ColorErase => {
+            let [r, g, b, a] = color_erase(rgb(backdrop), rgb(layer));
+            let erased = [r, g, b, a * backdrop_alpha];
+            let mut pixel = [0.0; 4];
+            for c in 0..4 {
+                pixel[c] = backdrop[c] + (erased[c] - backdrop[c]) * layer_alpha;
+            }
+            if pixel[3] > 0.0 {
+                // mix the colors weighted by their alpha
+                for c in 0..3 {
+                    pixel[c] = (backdrop[c] * backdrop_alpha * (1.0 - layer_alpha)
+                        + erased[c] * erased[3] * layer_alpha)
+                        / pixel[3];
+                }
+            }
+            pixel
+        }
+        _ => {
+            let (alpha, weights) = match composite_mode.or(mode.composite_mode()) {
+                // weights of the layer, the backdrop and the blended colors
+                LayerCompositeMode::ClipToBackdrop => {
+                    (backdrop_alpha, [0.0, 1.0 - layer_alpha, layer_alpha])
+                }
+                LayerCompositeMode::ClipToLayer => {
+                    (layer_alpha, [1.0 - backdrop_alpha, 0.0, backdrop_alpha])
+                }
+                LayerCompositeMode::Intersection => (layer_alpha * backdrop_alpha, [0.0, 0.0, 1.0]),
+                LayerCompositeMode::Union | LayerCompositeMode::Auto => {
+                    let alpha = layer_alpha + backdrop_alpha * (1.0 - layer_alpha);
+                    let weights = [
+                        layer_alpha * (1.0 - backdrop_alpha),
+                        backdrop_alpha * (1.0 - layer_alpha),
+                        layer_alpha * backdrop_alpha,
+                    ];
+                    (
+                        alpha,
+                        weights.map(|w| if alpha > 0.0 { w / alpha } else { 0.0 }),
+                    )
+                }
+            };
+            if alpha <= 0.0 {
+                return [0.0; 4];
+            }
+            let mut pixel = [0.0, 0.0, 0.0, alpha];
+            for c in 0..3 {
+                pixel[c] =
+                    layer[c] * weights[0] + backdrop[c] * weights[1] + blended[c] * weights[2];
+            }
+            pixel
+        }
+    }
+}
+
+/// Remove `color` from `backdrop`, making it as transparent as possible while keeping its
+/// appearance over `color`.
+fn color_erase(backdrop: [f32; 3], color: [f32; 3]) -> [f32; 4] {
+    let mut alpha: f32 = 0.0;
+    for c in 0..3 {
+        let a = if backdrop[c] > color[c] {
+            (backdrop[c] - color[c]) / (1.0 - color[c])
+        } else if backdrop[c] < color[c] {
+            (color[c] - backdrop[c]) / color[c]
+        } else {
+            0.0
+        };
+        alpha = alpha.max(a);
+    }
+    if alpha <= 0.0 {
+        return [0.0; 4];
+    }
+    let mut pixel = [0.0, 0.0, 0.0, alpha];
+    for c in 0..3 {
+        pixel[c] = (backdrop[c] - color[c]) / alpha + color[c];
+    }
+    pixel
+}
+
+/// A pseudo random value between 0.0 and 1.0 for a canvas position, the pixel of a layer in
+/// dissolve mode is drawn when its alpha is above it.
+///
+/// The noise is stable for a position but isn't the one of GIMP.
+pub fn dissolve_threshold(x: i32, y: i32) -> f32 {
+    let mut hash = (x as u32).wrapping_mul(0x9e37_79b9) ^ (y as u32).wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x7feb_352d);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x846c_a68b);
+    hash ^= hash >> 16;
+    (hash >> 8) as f32 / (1 << 24) as f32
+}
+
+fn rgb_to_hsv(color: [f32; 3]) -> (f32, f32, f32) {
+    let max = color[0].max(color[1]).max(color[2]);
+    let min = color[0].min(color[1]).min(color[2]);
+    let delta = max - min;
+    let saturation = if max > 0.0 { delta / max } else { 0.0 };
+    (hue(color, max, delta), saturation, max)
+}
+
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let chroma = value * saturation;
+    hue_to_rgb(hue, chroma, value - chroma)
+}
+
+fn rgb_to_hsl(color: [f32; 3]) -> (f32, f32, f32) {
+    let max = color[0].max(color[1]).max(color[2]);
+    let min = color[0].min(color[1]).min(color[2]);
+    let delta = max - min;
+    let lightness = (max + min) / 2.0;
+    let saturation = if delta > 0.0 {
+        delta / (1.0 - (2.0 * lightness - 1.0).abs())
+    } else {
+        0.0
+    };
+    (hue(color, max, delta), saturation, lightness)
+}
+
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> [f32; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    hue_to_rgb(hue, chroma, lightness - chroma / 2.0)
+}
+
+/// Hue between 0.0 and 6.0.
+fn hue(color: [f32; 3], max: f32, delta: f32) -> f32 {
+    if delta <= 0.0 {
+        0.0
+    } else if max == color[0] {
+        ((color[1] - color[2]) / delta).rem_euclid(6.0)
+    } else if max == color[1] {
+        (color[2] - color[0]) / delta + 2.0
+    } else {
+        (color[0] - color[1]) / delta + 4.0
+    }
+}
+
+fn hue_to_rgb(hue: f32, chroma: f32, min: f32) -> [f32; 3] {
+    let x = chroma * (1.0 - (hue.rem_euclid(2.0) - 1.0).abs());
+    let [r, g, b] = match hue as u32 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    [r + min, g + min, b + min]
+}
+
+/// Convert a gamma corrected sRGB color to CIE LCh(ab), with a D65 white point.
+fn rgb_to_lch(color: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = color.map(srgb_to_linear);
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    let (l, a, b) = (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz));
+    [l, a.hypot(b), b.atan2(a)]
+}
+
+fn lch_to_rgb(lch: [f32; 3]) -> [f32; 3] {
+    let [l, c, h] = lch;
+    let (a, b) = (c * h.cos(), c * h.sin());
+    let fy = (l + 16.0) / 116.0;
+    let (fx, fz) = (fy + a / 500.0, fy - b / 200.0);
+    let f_inv = |t: f32| {
+        if t.powi(3) > 216.0 / 24389.0 {
+            t.powi(3)
+        } else {
+            (116.0 * t - 16.0) * 27.0 / 24389.0
+        }
+    };
+    let (x, y, z) = (f_inv(fx) * 0.95047, f_inv(fy), f_inv(fz) * 1.08883);
+    let r = 3.2406 * x - 1.5372 * y - 0.4986 * z;
+    let g = -0.9689 * x + 1.8758 * y + 0.0415 * z;
+    let b = 0.0557 * x - 0.2040 * y + 1.0570 * z;
+    [r, g, b].map(linear_to_srgb)
+}
+
+/// Convert a gamma corrected sRGB channel to linear light.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a linear light channel to gamma corrected sRGB.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
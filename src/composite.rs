@@ -0,0 +1,211 @@
+//! Composite the layers of an image into a single one, the way GIMP displays it.
+
+use crate::blend::{self, Compositing};
+use crate::data::layer::Layer;
+use crate::data::mode::LayerMode;
+use crate::data::pixeldata::PixelData;
+use crate::data::rect::Rect;
+use crate::data::rgba::RgbaPixel;
+use crate::data::tree::LayerNode;
+use crate::data::xcf::Xcf;
+
+/// Pixels of a part of the canvas, with channels between 0.0 and 1.0 (not premultiplied).
+struct Canvas {
+    area: Rect,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Canvas {
+    fn new(area: Rect) -> Self {
+        Canvas {
+            area,
+            pixels: vec![[0.0; 4]; area.width as usize * area.height as usize],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y - self.area.y) as usize * self.area.width as usize + (x - self.area.x) as usize
+    }
+
+    /// Composite `source` onto the pixel at `index` the way a layer is, `opacity` scales the
+    /// alpha of `source`.
+    fn composite(
+        &mut self,
+        index: usize,
+        compositing: &Compositing,
+        source: [f32; 4],
+        opacity: f32,
+    ) {
+        self.pixels[index] = compositing.composite(self.pixels[index], source, opacity);
+    }
+
+    /// Convert to 8-bit pixels, gamma corrected unless `linear`.
+    fn to_pixel_data(&self, region: Rect, linear: bool) -> PixelData {
+        let mut pixels =
+            vec![RgbaPixel::new(0, 0, 0, 0); region.width as usize * region.height as usize];
+        let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let encode = |v: f32| to_u8(if linear { v } else { blend::linear_to_srgb(v) });
+        for y in self.area.y..self.area.y + self.area.height as i32 {
+            for x in self.area.x..self.area.x + self.area.width as i32 {
+                let [r, g, b, a] = self.pixels[self.index(x, y)];
+                if a <= 0.0 {
+                    continue;
+                }
+                let index =
+                    (y - region.y) as usize * region.width as usize + (x - region.x) as usize;
+                pixels[index] = RgbaPixel::new(encode(r), encode(g), encode(b), to_u8(a));
+            }
+        }
+        PixelData {
+            width: region.width,
+            height: region.height,
+            pixels,
+        }
+    }
+}
+
+/// Value of the mask of `layer` at a canvas position, 1.0 when the mask isn't applied.
+fn mask_value(layer: &Layer, x: i32, y: i32) -> f32 {
+    match (&layer.mask, layer.apply_mask()) {
+        (Some(mask), true) => {
+            let (ox, oy) = layer.offsets();
+            let (x, y) = (x - ox, y - oy);
+            if x < 0 || y < 0 {
+                return 0.0;
+            }
+            f32::from(mask.pixel(x as u32, y as u32).unwrap_or(0)) / 255.0
+        }
+        _ => 1.0,
+    }
+}
+
+/// Composite the visible layers of `nodes` from bottom to top onto `canvas`, the pixels of the
+/// layers are gamma corrected unless `linear`.
+fn render(nodes: &[LayerNode], canvas: &mut Canvas, linear: bool) {
+    let area = canvas.area;
+    // layers are stored from top to bottom
+    for node in nodes.iter().rev() {
+        let layer = node.layer;
+        if !layer.is_visible() {
+            continue;
+        }
+        let opacity = layer.opacity();
+        let compositing = layer.compositing();
+        let mode = compositing.mode;
+        if node.is_group() && mode == LayerMode::PassThrough {
+            // the children are composited onto the backdrop directly, the opacity and mask of
+            // the group fade the result in
+            let mut group = Canvas {
+                area,
+                pixels: canvas.pixels.clone(),
+            };
+            render(&node.children, &mut group, linear);
+            for y in area.y..area.y + area.height as i32 {
+                for x in area.x..area.x + area.width as i32 {
+                    let index = canvas.index(x, y);
+                    let fade = opacity * mask_value(layer, x, y);
+                    let (backdrop, result) = (canvas.pixels[index], group.pixels[index]);
+                    canvas.pixels[index] = interpolate(backdrop, result, fade);
+                }
+            }
+        } else if node.is_group() {
+            // the children of a group are composited on their own before the group is
+            let mut group = Canvas::new(area);
+            render(&node.children, &mut group, linear);
+            for y in area.y..area.y + area.height as i32 {
+                for x in area.x..area.x + area.width as i32 {
+                    let index = canvas.index(x, y);
+                    let mut source = group.pixels[index];
+                    dissolve(mode, &mut source, x, y, opacity);
+                    canvas.composite(
+                        index,
+                        &compositing,
+                        source,
+                        opacity * mask_value(layer, x, y),
+                    );
+                }
+            }
+        } else {
+            let Some(visible) = layer.bounds().intersection(&area) else {
+                continue;
+            };
+            for y in visible.y..visible.y + visible.height as i32 {
+                for x in visible.x..visible.x + visible.width as i32 {
+                    let Some(pixel) = layer.canvas_pixel(x, y) else {
+                        continue;
+                    };
+                    let [r, g, b, a] = pixel.0.map(|c| f32::from(c) / 255.0);
+                    let mut source = if linear {
+                        [r, g, b, a]
+                    } else {
+                        let [r, g, b] = [r, g, b].map(blend::srgb_to_linear);
+                        [r, g, b, a]
+                    };
+                    dissolve(mode, &mut source, x, y, opacity);
+                    let index = canvas.index(x, y);
+                    canvas.composite(
+                        index,
+                        &compositing,
+                        source,
+                        opacity * mask_value(layer, x, y),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Interpolate two pixels, taking their alpha into account.
+fn interpolate(from: [f32; 4], to: [f32; 4], amount: f32) -> [f32; 4] {
+    let alpha = from[3] + (to[3] - from[3]) * amount;
+    if alpha <= 0.0 {
+        return [0.0; 4];
+    }
+    let mut pixel = [0.0, 0.0, 0.0, alpha];
+    for c in 0..3 {
+        pixel[c] = (from[c] * from[3] * (1.0 - amount) + to[c] * to[3] * amount) / alpha;
+    }
+    pixel
+}
+
+/// In dissolve mode, pixels are either drawn or not depending on their alpha.
+fn dissolve(mode: LayerMode, source: &mut [f32; 4], x: i32, y: i32, opacity: f32) {
+    if mode == LayerMode::Dissolve {
+        // the opacity is applied afterwards, compensate it
+        let drawn = source[3] * opacity > blend::dissolve_threshold(x, y);
+        source[3] = if drawn && opacity > 0.0 {
+            1.0 / opacity
+        } else {
+            0.0
+        };
+    }
+}
+
+impl Xcf {
+    /// Composite the visible layers into an image of the size of the canvas, like GIMP exports
+    /// it.
+    ///
+    /// Layers are stacked from the bottom one up, at their offsets, with their mode, their
+    /// opacity and their mask when it is applied. Groups are composited on their own first,
+    /// unless they are in pass through mode. Like in GIMP, colors are blended and composited
+    /// in linear or perceptual RGB depending on the layer, see [`Layer::compositing`].
+    pub fn flatten(&self) -> PixelData {
+        self.flatten_region(self.bounds())
+    }
+
+    /// Composite the visible layers like [`Xcf::flatten`], for a part of the canvas only.
+    ///
+    /// The returned pixels have the size of `region`, the parts of it outside of the canvas are
+    /// transparent.
+    pub fn flatten_region(&self, region: Rect) -> PixelData {
+        match region.intersection(&self.bounds()) {
+            Some(area) => {
+                let mut canvas = Canvas::new(area);
+                let linear = self.header.precision.is_linear();
+                render(&self.layer_tree(), &mut canvas, linear);
+                canvas.to_pixel_data(region, linear)
+            }
+            None => Canvas::new(Rect::new(region.x, region.y, 0, 0)).to_pixel_data(region, false),
+        }
+    }
+}
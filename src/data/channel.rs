@@ -0,0 +1,14 @@
+use crate::{NativePixelData, Property};
+
+/// A channel of the image, or the mask of a layer.
+#[derive(Debug, PartialEq)]
+pub struct Channel {
+    pub width: u32,
+    pub height: u32,
+    pub name: String,
+    pub properties: Vec<Property>,
+    /// One 8-bit value per pixel, row by row.
+    pub pixels: Vec<u8>,
+    /// Pixels as they are stored in the file, `None` for channels that were built by hand.
+    pub native: Option<NativePixelData>,
+}
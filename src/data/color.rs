@@ -19,3 +19,36 @@ impl ColorType {
         })
     }
 }
+
+/// The color tag of an item, shown in the layers dialog of GIMP.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ColorTag {
+    None = 0,
+    Blue = 1,
+    Green = 2,
+    Yellow = 3,
+    Orange = 4,
+    Brown = 5,
+    Red = 6,
+    Violet = 7,
+    Gray = 8,
+}
+
+impl ColorTag {
+    pub(crate) fn new(tag: u32) -> Result<ColorTag, Error> {
+        use self::ColorTag::*;
+        Ok(match tag {
+            0 => None,
+            1 => Blue,
+            2 => Green,
+            3 => Yellow,
+            4 => Orange,
+            5 => Brown,
+            6 => Red,
+            7 => Violet,
+            8 => Gray,
+            _ => return Err(Error::InvalidFormat),
+        })
+    }
+}
@@ -0,0 +1,11 @@
+/// The tiles of a layer or a channel as they are laid out in the file, read without decoding
+/// them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Hierarchy {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per pixel.
+    pub bpp: u32,
+    /// Pointers to the tiles, row by row, followed by the null pointer which ends the list.
+    pub tile_pointers: Vec<u64>,
+}
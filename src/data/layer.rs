@@ -1,4 +1,4 @@
-use crate::{LayerColorType, PixelData, Property};
+use crate::{Channel, LayerColorType, NativePixelData, PixelData, Property};
 
 #[derive(Debug, PartialEq)]
 pub struct Layer {
@@ -7,5 +7,9 @@ pub struct Layer {
     pub kind: LayerColorType,
     pub name: String,
     pub properties: Vec<Property>,
+    /// Pixels converted to RGBA.
     pub pixels: PixelData,
+    /// Pixels as they are stored in the file, `None` for layers that were built by hand.
+    pub native: Option<NativePixelData>,
+    pub mask: Option<Channel>,
 }
@@ -0,0 +1,43 @@
+use std::cell::{OnceCell, RefCell};
+
+use crate::data::hierarchy::Hierarchy;
+use crate::{Channel, Layer, LayerColorType, Property, XcfHeader};
+
+/// An XCF file whose pixels are only decoded when they are asked for, see
+/// [`Xcf::load_lazy`](crate::Xcf::load_lazy).
+#[derive(Debug)]
+pub struct LazyXcf<R> {
+    pub header: XcfHeader,
+    /// List of layers in the XCF file, in the order they are stored in the file.
+    pub layers: Vec<LayerHandle>,
+    /// List of channels in the XCF file, in the order they are stored in the file.
+    pub channels: Vec<ChannelHandle>,
+    pub(crate) reader: RefCell<R>,
+}
+
+/// The name and properties of a layer, its pixels are decoded with
+/// [`LazyXcf::load_layer`](LazyXcf::load_layer).
+#[derive(Debug)]
+pub struct LayerHandle {
+    pub width: u32,
+    pub height: u32,
+    pub kind: LayerColorType,
+    pub name: String,
+    pub properties: Vec<Property>,
+    /// `None` for groups, their pixels aren't read.
+    pub hierarchy: Option<Hierarchy>,
+    pub mask: Option<ChannelHandle>,
+    pub(crate) layer: OnceCell<Layer>,
+}
+
+/// The name and properties of a channel, its pixels are decoded with
+/// [`LazyXcf::load_channel`](LazyXcf::load_channel).
+#[derive(Debug)]
+pub struct ChannelHandle {
+    pub width: u32,
+    pub height: u32,
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub hierarchy: Hierarchy,
+    pub(crate) channel: OnceCell<Channel>,
+}
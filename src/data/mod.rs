@@ -1,11 +1,18 @@
+pub mod channel;
 pub mod color;
 pub mod error;
 pub mod header;
+pub mod hierarchy;
 pub mod layer;
+pub mod lazy;
+pub mod mode;
+pub mod palette;
 pub mod pixeldata;
 pub mod precision;
 pub mod property;
+pub mod rect;
 pub mod rgba;
+pub mod tree;
 pub mod version;
 pub mod xcf;
 pub mod tiles;
\ No newline at end of file
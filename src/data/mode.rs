@@ -0,0 +1,257 @@
+use crate::Error;
+
+/// The mode used to blend a layer with the layers below it.
+///
+/// Legacy modes are the ones of GIMP 2.8 and older, which blend in perceptual RGB.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LayerMode {
+    NormalLegacy = 0,
+    Dissolve = 1,
+    BehindLegacy = 2,
+    MultiplyLegacy = 3,
+    ScreenLegacy = 4,
+    OverlayLegacy = 5,
+    DifferenceLegacy = 6,
+    AdditionLegacy = 7,
+    SubtractLegacy = 8,
+    DarkenOnlyLegacy = 9,
+    LightenOnlyLegacy = 10,
+    HsvHueLegacy = 11,
+    HsvSaturationLegacy = 12,
+    HslColorLegacy = 13,
+    HsvValueLegacy = 14,
+    DivideLegacy = 15,
+    DodgeLegacy = 16,
+    BurnLegacy = 17,
+    HardlightLegacy = 18,
+    SoftlightLegacy = 19,
+    GrainExtractLegacy = 20,
+    GrainMergeLegacy = 21,
+    ColorEraseLegacy = 22,
+    Overlay = 23,
+    LchHue = 24,
+    LchChroma = 25,
+    LchColor = 26,
+    LchLightness = 27,
+    Normal = 28,
+    Behind = 29,
+    Multiply = 30,
+    Screen = 31,
+    Difference = 32,
+    Addition = 33,
+    Subtract = 34,
+    DarkenOnly = 35,
+    LightenOnly = 36,
+    HsvHue = 37,
+    HsvSaturation = 38,
+    HslColor = 39,
+    HsvValue = 40,
+    Divide = 41,
+    Dodge = 42,
+    Burn = 43,
+    Hardlight = 44,
+    Softlight = 45,
+    GrainExtract = 46,
+    GrainMerge = 47,
+    VividLight = 48,
+    PinLight = 49,
+    LinearLight = 50,
+    HardMix = 51,
+    Exclusion = 52,
+    LinearBurn = 53,
+    LumaDarkenOnly = 54,
+    LumaLightenOnly = 55,
+    Luminance = 56,
+    ColorErase = 57,
+    Erase = 58,
+    Merge = 59,
+    Split = 60,
+    PassThrough = 61,
+}
+
+impl LayerMode {
+    pub(crate) fn new(mode: u32) -> Result<LayerMode, Error> {
+        use self::LayerMode::*;
+        Ok(match mode {
+            0 => NormalLegacy,
+            1 => Dissolve,
+            2 => BehindLegacy,
+            3 => MultiplyLegacy,
+            4 => ScreenLegacy,
+            5 => OverlayLegacy,
+            6 => DifferenceLegacy,
+            7 => AdditionLegacy,
+            8 => SubtractLegacy,
+            9 => DarkenOnlyLegacy,
+            10 => LightenOnlyLegacy,
+            11 => HsvHueLegacy,
+            12 => HsvSaturationLegacy,
+            13 => HslColorLegacy,
+            14 => HsvValueLegacy,
+            15 => DivideLegacy,
+            16 => DodgeLegacy,
+            17 => BurnLegacy,
+            18 => HardlightLegacy,
+            19 => SoftlightLegacy,
+            20 => GrainExtractLegacy,
+            21 => GrainMergeLegacy,
+            22 => ColorEraseLegacy,
+            23 => Overlay,
+            24 => LchHue,
+            25 => LchChroma,
+            26 => LchColor,
+            27 => LchLightness,
+            28 => Normal,
+            29 => Behind,
+            30 => Multiply,
+            31 => Screen,
+            32 => Difference,
+            33 => Addition,
+            34 => Subtract,
+            35 => DarkenOnly,
+            36 => LightenOnly,
+            37 => HsvHue,
+            38 => HsvSaturation,
+            39 => HslColor,
+            40 => HsvValue,
+            41 => Divide,
+            42 => Dodge,
+            43 => Burn,
+            44 => Hardlight,
+            45 => Softlight,
+            46 => GrainExtract,
+            47 => GrainMerge,
+            48 => VividLight,
+            49 => PinLight,
+            50 => LinearLight,
+            51 => HardMix,
+            52 => Exclusion,
+            53 => LinearBurn,
+            54 => LumaDarkenOnly,
+            55 => LumaLightenOnly,
+            56 => Luminance,
+            57 => ColorErase,
+            58 => Erase,
+            59 => Merge,
+            60 => Split,
+            61 => PassThrough,
+            _ => return Err(Error::InvalidFormat),
+        })
+    }
+
+    /// Whether the mode is one of GIMP 2.8 and older, dissolve is shared by both.
+    pub fn is_legacy(&self) -> bool {
+        *self != LayerMode::Dissolve && (*self as u32) <= LayerMode::ColorEraseLegacy as u32
+    }
+
+    /// The color space GIMP blends the colors of a layer in with this mode, when the layer
+    /// leaves it on auto.
+    pub fn blend_space(&self) -> LayerColorSpace {
+        use self::LayerMode::*;
+        match self {
+            _ if self.is_legacy() => LayerColorSpace::RgbPerceptual,
+            Screen | Overlay | Dodge | Burn | Hardlight | Softlight | GrainExtract | GrainMerge
+            | VividLight | PinLight | LinearLight | HardMix | Exclusion | LinearBurn | HsvHue
+            | HsvSaturation | HslColor | HsvValue => LayerColorSpace::RgbPerceptual,
+            LchHue | LchChroma | LchColor | LchLightness => LayerColorSpace::Lab,
+            _ => LayerColorSpace::RgbLinear,
+        }
+    }
+
+    /// The color space GIMP composites a layer in with this mode, when the layer leaves it on
+    /// auto.
+    pub fn composite_space(&self) -> LayerColorSpace {
+        if self.is_legacy() {
+            LayerColorSpace::RgbPerceptual
+        } else {
+            LayerColorSpace::RgbLinear
+        }
+    }
+
+    /// How GIMP combines the alpha of a layer with the one of its backdrop with this mode,
+    /// when the layer leaves it on auto.
+    pub fn composite_mode(&self) -> LayerCompositeMode {
+        use self::LayerMode::*;
+        match self {
+            NormalLegacy | Normal | Dissolve | BehindLegacy | Behind | PassThrough => {
+                LayerCompositeMode::Union
+            }
+            _ => LayerCompositeMode::ClipToBackdrop,
+        }
+    }
+}
+
+/// The color space in which a layer is blended or composited.
+///
+/// GIMP stores "auto" as a negative value, the space then depends on the mode of the layer,
+/// see [`LayerMode::blend_space`] and [`LayerMode::composite_space`].
+#[repr(u32)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LayerColorSpace {
+    Auto = u32::MAX,
+    RgbLinear = 1,
+    RgbPerceptual = 2,
+    Lab = 3,
+}
+
+impl LayerColorSpace {
+    pub(crate) fn new(space: u32) -> Result<LayerColorSpace, Error> {
+        use self::LayerColorSpace::*;
+        Ok(match space as i32 {
+            i32::MIN..=0 => Auto,
+            1 => RgbLinear,
+            2 => RgbPerceptual,
+            3 => Lab,
+            _ => return Err(Error::InvalidFormat),
+        })
+    }
+
+    /// Resolve "auto" to `default`.
+    pub fn or(self, default: LayerColorSpace) -> LayerColorSpace {
+        match self {
+            LayerColorSpace::Auto => default,
+            space => space,
+        }
+    }
+}
+
+/// Which parts of a layer and its backdrop are kept when they are composited.
+///
+/// GIMP stores "auto" as a negative value, the composite mode then depends on the mode of the
+/// layer, see [`LayerMode::composite_mode`].
+#[repr(u32)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LayerCompositeMode {
+    Auto = u32::MAX,
+    /// Both the layer and the backdrop, like normal mode.
+    Union = 1,
+    /// Only where the backdrop is, the alpha of the backdrop is kept.
+    ClipToBackdrop = 2,
+    /// Only where the layer is, the alpha of the layer is kept.
+    ClipToLayer = 3,
+    /// Only where both are, their alphas are multiplied.
+    Intersection = 4,
+}
+
+impl LayerCompositeMode {
+    pub(crate) fn new(mode: u32) -> Result<LayerCompositeMode, Error> {
+        use self::LayerCompositeMode::*;
+        Ok(match mode as i32 {
+            i32::MIN..=0 => Auto,
+            1 => Union,
+            2 => ClipToBackdrop,
+            3 => ClipToLayer,
+            4 => Intersection,
+            _ => return Err(Error::InvalidFormat),
+        })
+    }
+
+    /// Resolve "auto" to `default`.
+    pub fn or(self, default: LayerCompositeMode) -> LayerCompositeMode {
+        match self {
+            LayerCompositeMode::Auto => default,
+            mode => mode,
+        }
+    }
+}
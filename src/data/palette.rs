@@ -0,0 +1,31 @@
+use crate::RgbaPixel;
+
+/// The colormap of an indexed image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// A palette going from black to white, used by GIMP in place of the colormaps of version 0
+    /// files, which were not saved correctly.
+    pub fn grayscale(len: usize) -> Palette {
+        Palette {
+            colors: (0..len).map(|i| [i as u8; 3]).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Get the color at `index` as an opaque pixel.
+    pub fn color(&self, index: u8) -> Option<RgbaPixel> {
+        let [r, g, b] = *self.colors.get(index as usize)?;
+        Some(RgbaPixel::new(r, g, b, 255))
+    }
+}
@@ -1,4 +1,4 @@
-use crate::RgbaPixel;
+use crate::{Precision, RgbaPixel};
 
 // TODO: Make this an enum? We should store a buffer that matches the channels present.
 #[derive(Clone, Debug, PartialEq)]
@@ -7,3 +7,27 @@ pub struct PixelData {
     pub height: u32,
     pub pixels: Vec<RgbaPixel>,
 }
+
+/// Pixel data in the layout it is stored in the file: `bpp` bytes per pixel, the channels of a
+/// pixel following each other, each channel being a big-endian value of the `precision`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NativePixelData {
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u32,
+    pub precision: Precision,
+    pub data: Vec<u8>,
+}
+
+/// The components of pixel data, at the precision they are stored with in the file.
+///
+/// Half floats are kept as their bits, use [`Components::to_f32`] to read them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Components {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    F16(Vec<u16>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
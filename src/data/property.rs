@@ -1,54 +1,76 @@
 use byteorder::{BigEndian, ReadBytesExt};
-use std::io::Read;
+use std::io::{self, Read};
 
 use crate::data::error::Error;
+use crate::data::palette::Palette;
+use crate::data::version::Version;
 use crate::data::xcf::XcfCompression;
 use crate::RgbaPixel;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ResolutionProperty {
     pub xres: f32,
     pub yres: f32,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+pub struct GuideProperty {
+    pub position: i32,
+    /// 1 for an horizontal guide, 2 for a vertical one.
+    pub orientation: u8,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct ParasiteProperty {
     pub name: String,
     pub flags: u32,
     pub data: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum PropertyPayload {
-    ColorMap { colors: usize },
+    ColorMap(Palette),
     End,
     Compression(XcfCompression),
     ResolutionProperty(ResolutionProperty),
     Tatoo(u32),
     Unit(u32),
     Parasites(Vec<ParasiteProperty>),
+    Guides(Vec<GuideProperty>),
+    FloatingSelection(u64),
     // layer property
     ActiveLayer(),
     OpacityLayer(RgbaPixel),
-    FloatOpacityLayer(),
-    VisibleLayer(),
+    FloatOpacityLayer(f32),
+    VisibleLayer(u32),
     LinkedLayer(u32),
     ColorTagLayer(u32),
     LockContentLayer(u32),
     LockAlphaLayer(u32),
     LockPositionLayer(u32),
+    LockVisibilityLayer(u32),
+    TextLayerFlagsLayer(u32),
     ApplyMaskLayer(u32),
     EditMaskLayer(u32),
     ShowMaskLayer(u32),
-    OffsetsLayer(u32, u32),
+    OffsetsLayer(i32, i32),
     ModeLayer(u32),
     BlendSpaceLayer(u32),
     CompositeSpaceLayer(u32),
     CompositeModeLayer(u32),
+    GroupItemLayer(),
+    ItemPathLayer(Vec<u32>),
+    GroupItemFlagsLayer(u32),
+    // channel property
+    ActiveChannel(),
+    SelectionChannel(),
+    ShowMaskedChannel(u32),
+    ColorChannel([u8; 3]),
+    FloatColorChannel([f32; 3]),
     Unknown(Vec<u8>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Property {
     pub kind: PropertyIdentifier,
     pub length: usize,
@@ -60,19 +82,30 @@ impl Property {
     // property length... (for known properties)
     fn _guess_size(&self) -> usize {
         match self.payload {
-            PropertyPayload::ColorMap { colors, .. } => {
+            PropertyPayload::ColorMap(ref palette) => {
                 /* apparently due to a GIMP bug sometimes self.length will be n + 4 */
-                3 * colors + 4
+                3 * palette.len() + 4
             }
             // this is the best we can do otherwise
             _ => self.length,
         }
     }
 
-    fn parse<R: Read>(mut rdr: R) -> Result<Property, Error> {
+    fn parse<R: Read>(mut rdr: R, version: Version) -> Result<Property, Error> {
         let kind = PropertyIdentifier::new(rdr.read_u32::<BigEndian>()?);
         let length = rdr.read_u32::<BigEndian>()? as usize;
-        let payload = PropertyPayload::parse(&mut rdr, kind, length)?;
+        let payload = if let PropertyIdentifier::PropColormap = kind {
+            // the length GIMP stores for a colormap doesn't match its size
+            PropertyPayload::parse(&mut rdr, kind, length, version)?
+        } else {
+            // decode from the whole property, so padded payloads don't shift the next ones
+            let mut data = Vec::new();
+            (&mut rdr).take(length as u64).read_to_end(&mut data)?;
+            if data.len() != length {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            PropertyPayload::parse(&data[..], kind, length, version)?
+        };
         Ok(Property {
             kind,
             length,
@@ -80,10 +113,11 @@ impl Property {
         })
     }
 
-    pub fn parse_list<R: Read>(mut rdr: R) -> Result<Vec<Property>, Error> {
+    /// Parse the properties of a file of `version` up to their end.
+    pub fn parse_list<R: Read>(mut rdr: R, version: Version) -> Result<Vec<Property>, Error> {
         let mut props = Vec::new();
         loop {
-            let p = Property::parse(&mut rdr)?;
+            let p = Property::parse(&mut rdr, version)?;
             if let PropertyIdentifier::PropEnd = p.kind {
                 break;
             }
@@ -99,7 +133,7 @@ macro_rules! prop_ident_gen {
         #[derive(Debug, Clone, Copy, PartialEq)]
         #[repr(u32)]
         pub enum PropertyIdentifier {
-            //Unknown(u32),
+            Unknown(u32),
             $(
                 $prop:ident = $val:expr
             ),+,
@@ -114,7 +148,7 @@ macro_rules! prop_ident_gen {
             // we have to put this at the end, since otherwise it will try to have value zero,
             // we really don't care what it is as long as it doesn't conflict with anything else
             // (however in the macro we have to put it first since it's a parsing issue)
-            //Unknown(u32),
+            Unknown(u32),
         }
 
         impl PropertyIdentifier {
@@ -123,8 +157,17 @@ macro_rules! prop_ident_gen {
                     $(
                         $val => PropertyIdentifier::$prop
                     ),+,
-                    _ => todo!()
-                    //_ => PropertyIdentifier::Unknown(prop),
+                    _ => PropertyIdentifier::Unknown(prop),
+                }
+            }
+
+            /// Get the number identifying the property in the file.
+            pub fn to_u32(&self) -> u32 {
+                match self {
+                    $(
+                        PropertyIdentifier::$prop => $val
+                    ),+,
+                    PropertyIdentifier::Unknown(prop) => *prop,
                 }
             }
         }
@@ -135,6 +178,7 @@ prop_ident_gen! {
     #[derive(Debug, Clone, Copy, PartialEq)]
     #[repr(u32)]
     pub enum PropertyIdentifier {
+        Unknown(u32),
         PropEnd = 0,
         PropColormap = 1,
         PropActiveLayer = 2,
@@ -149,8 +193,11 @@ prop_ident_gen! {
         PropApplyMask = 11,
         PropEditMask = 12,
         PropShowMask = 13,
+        PropShowMasked = 14,
         PropOffsets = 15,
+        PropColor = 16,
         PropCompression = 17,
+        // PROP_GUIDES
         TypeIdentification = 18,
         PropResolution = 19,
         PropTattoo = 20,
@@ -162,6 +209,9 @@ prop_ident_gen! {
         PropTextLayerFlags = 26,
         PropOldSamplePoints = 27,
         PropLockContent = 28,
+        PropGroupItem = 29,
+        PropItemPath = 30,
+        PropGroupItemFlags = 31,
         PropLockPosition = 32,
         PropFloatOpacity = 33,
         PropColorTag = 34,
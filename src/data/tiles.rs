@@ -1,4 +1,8 @@
 use crate::Layer;
+use crate::data::hierarchy::Hierarchy;
+use crate::data::lazy::LazyXcf;
+use crate::data::rect::Rect;
+use crate::data::xcf::XcfCompression;
 
 #[derive(Debug, PartialEq)]
 pub struct Tiles {
@@ -17,4 +21,28 @@ impl Tiles {
             nb: nb_width * nb_height
         }
     }
-}
\ No newline at end of file
+}
+
+/// A tile of a layer or a channel, as it is stored in the file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Tile {
+    /// Position of the tile in the grid of tiles.
+    pub column: u32,
+    pub row: u32,
+    /// Pixels covered by the tile, in the coordinates of the layer.
+    pub rect: Rect,
+    pub compression: XcfCompression,
+    /// Bytes per pixel.
+    pub bpp: u32,
+    /// The compressed bytes of the tile.
+    pub data: Vec<u8>,
+}
+
+/// Iterator over the tiles of a layer or a channel, row by row, see
+/// [`LazyXcf::layer_tiles`](crate::data::lazy::LazyXcf::layer_tiles).
+#[derive(Debug)]
+pub struct TileIter<'a, R> {
+    pub(crate) xcf: &'a LazyXcf<R>,
+    pub(crate) hierarchy: Option<&'a Hierarchy>,
+    pub(crate) index: usize,
+}
@@ -0,0 +1,13 @@
+use crate::Layer;
+
+/// A layer of the image, along with the layers it contains when it is a group.
+///
+/// See [`Xcf::layer_tree`](crate::data::xcf::Xcf::layer_tree).
+#[derive(Debug, PartialEq)]
+pub struct LayerNode<'a> {
+    pub layer: &'a Layer,
+    /// Index of the layer in [`Xcf::layers`](crate::data::xcf::Xcf::layers).
+    pub index: usize,
+    /// Layers of the group, from top to bottom.
+    pub children: Vec<LayerNode<'a>>,
+}
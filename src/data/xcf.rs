@@ -1,6 +1,6 @@
-use crate::{Layer, XcfHeader};
+use crate::{Channel, Error, Layer, XcfHeader};
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum XcfCompression {
     None = 0,
     Rle = 1,
@@ -9,8 +9,19 @@ pub enum XcfCompression {
 }
 
 impl XcfCompression {
+    pub(crate) fn new(kind: u8) -> Result<XcfCompression, Error> {
+        use self::XcfCompression::*;
+        Ok(match kind {
+            0 => None,
+            1 => Rle,
+            2 => Zlib,
+            3 => Fractal,
+            _ => return Err(Error::InvalidFormat),
+        })
+    }
+
     pub fn to_u8(&self) -> u8 {
-        self.clone() as u8
+        *self as u8
     }
 }
 
@@ -22,4 +33,8 @@ pub struct Xcf {
     ///
     /// See [`Xcf::layer`](Xcf::layer) to get a layer by name.
     pub layers: Vec<Layer>,
+    /// List of channels in the XCF file, in the order they are stored in the file.
+    ///
+    /// See [`Xcf::channel`](Xcf::channel) to get a channel by name.
+    pub channels: Vec<Channel>,
 }
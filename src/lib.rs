@@ -10,28 +10,55 @@
 #[macro_use]
 extern crate derive_error;
 extern crate byteorder;
+extern crate flate2;
 
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom};
 
 use byteorder::{BigEndian, ReadBytesExt};
 use std::borrow::Cow;
-use std::cmp;
+use std::cell::{OnceCell, RefCell};
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+pub mod blend;
+mod composite;
 pub mod create;
 pub mod data;
 pub mod parser;
 pub mod rle;
+mod save;
 
+use crate::blend::Compositing;
 use crate::data::{
-    color::ColorType, error::Error, header::XcfHeader, layer::Layer, pixeldata::PixelData,
-    precision::Precision, property::PropertyPayload, rgba::RgbaPixel, version::Version, xcf::Xcf,
+    channel::Channel,
+    color::{ColorTag, ColorType},
+    error::Error,
+    header::XcfHeader,
+    hierarchy::Hierarchy,
+    layer::Layer,
+    lazy::{ChannelHandle, LayerHandle, LazyXcf},
+    mode::{LayerColorSpace, LayerCompositeMode, LayerMode},
+    palette::Palette,
+    pixeldata::{Components, NativePixelData, PixelData},
+    precision::Precision,
+    property::PropertyPayload,
+    rect::Rect,
+    rgba::RgbaPixel,
+    tiles::{Tile, TileIter},
+    tree::LayerNode,
+    version::Version,
+    xcf::Xcf,
+    xcf::XcfCompression,
 };
 use crate::parser::ParseVersion;
 
-use crate::data::property::{Property, PropertyIdentifier};
+use crate::data::property::{
+    GuideProperty, ParasiteProperty, Property, PropertyIdentifier, ResolutionProperty,
+};
 
 impl Precision {
     fn parse<R: Read>(mut rdr: R, version: Version) -> Result<Self, Error> {
@@ -82,6 +109,89 @@ impl Precision {
             _ => return Err(Error::InvalidPrecision),
         })
     }
+
+    /// Get the number identifying the precision in files of `version`, the reverse of
+    /// [`Precision::parse`].
+    fn to_u32(self, version: Version) -> Result<u32, Error> {
+        use self::Precision::*;
+        Ok(match (version.num(), self) {
+            (4, NonLinearU8) => 0,
+            (4, NonLinearU16) => 1,
+            (4, LinearU32) => 2,
+            (4, LinearF16) => 3,
+            (4, LinearF32) => 4,
+            (5..=6, LinearU8) => 100,
+            (5..=6, NonLinearU8) => 150,
+            (5..=6, LinearU16) => 200,
+            (5..=6, NonLinearU16) => 250,
+            (5..=6, LinearU32) => 300,
+            (5..=6, NonLinearU32) => 350,
+            (5..=6, LinearF16) => 400,
+            (5..=6, NonLinearF16) => 450,
+            (5..=6, LinearF32) => 500,
+            (5..=6, NonLinearF32) => 550,
+            (7.., LinearU8) => 100,
+            (7.., NonLinearU8) => 150,
+            (7.., PerceptualU8) => 175,
+            (7.., LinearU16) => 200,
+            (7.., NonLinearU16) => 250,
+            (7.., PerceptualU16) => 275,
+            (7.., LinearU32) => 300,
+            (7.., NonLinearU32) => 350,
+            (7.., PerceptualU32) => 375,
+            (7.., LinearF16) => 500,
+            (7.., NonLinearF16) => 550,
+            (7.., PerceptualF16) => 575,
+            (7.., LinearF32) => 600,
+            (7.., NonLinearF32) => 650,
+            (7.., PerceptualF32) => 675,
+            (7.., LinearF64) => 700,
+            (7.., NonLinearF64) => 750,
+            (7.., PerceptualF64) => 775,
+            _ => return Err(Error::InvalidPrecision),
+        })
+    }
+
+    /// Number of bytes used by each channel of a pixel.
+    pub fn bytes_per_component(&self) -> u32 {
+        use self::Precision::*;
+        match self {
+            LinearU8 | NonLinearU8 | PerceptualU8 => 1,
+            LinearU16 | NonLinearU16 | PerceptualU16 | LinearF16 | NonLinearF16 | PerceptualF16 => {
+                2
+            }
+            LinearU32 | NonLinearU32 | PerceptualU32 | LinearF32 | NonLinearF32 | PerceptualF32 => {
+                4
+            }
+            LinearF64 | NonLinearF64 | PerceptualF64 => 8,
+        }
+    }
+
+    /// Whether the channels are floating point values rather than integers.
+    pub fn is_float(&self) -> bool {
+        use self::Precision::*;
+        matches!(
+            self,
+            LinearF16
+                | NonLinearF16
+                | PerceptualF16
+                | LinearF32
+                | NonLinearF32
+                | PerceptualF32
+                | LinearF64
+                | NonLinearF64
+                | PerceptualF64
+        )
+    }
+
+    /// Whether the channels store linear light, rather than gamma corrected values.
+    pub fn is_linear(&self) -> bool {
+        use self::Precision::*;
+        matches!(
+            self,
+            LinearU8 | LinearU16 | LinearU32 | LinearF16 | LinearF32 | LinearF64
+        )
+    }
 }
 
 impl PropertyPayload {
@@ -89,10 +199,116 @@ impl PropertyPayload {
         mut rdr: R,
         kind: PropertyIdentifier,
         length: usize,
+        version: Version,
     ) -> Result<PropertyPayload, Error> {
         use self::PropertyIdentifier::*;
         Ok(match kind {
             PropEnd => PropertyPayload::End,
+            PropColormap => {
+                let colors = rdr.read_u32::<BigEndian>()? as usize;
+                // GIMP refuses bigger palettes
+                if colors > 256 {
+                    return Err(Error::InvalidFormat);
+                }
+                // the size is computed from the number of colors, like GIMP does: it stores
+                // `colors + 4` as the length of the property
+                if version.num() == 0 {
+                    // version 0 files store a single byte per color, which GIMP ignores
+                    io::copy(&mut (&mut rdr).take(colors as u64), &mut io::sink())?;
+                    PropertyPayload::ColorMap(Palette::grayscale(colors))
+                } else {
+                    let mut data = vec![0; colors * 3];
+                    rdr.read_exact(&mut data)?;
+                    PropertyPayload::ColorMap(Palette {
+                        colors: data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+                    })
+                }
+            }
+            PropCompression => PropertyPayload::Compression(XcfCompression::new(rdr.read_u8()?)?),
+            PropResolution => PropertyPayload::ResolutionProperty(ResolutionProperty {
+                xres: rdr.read_f32::<BigEndian>()?,
+                yres: rdr.read_f32::<BigEndian>()?,
+            }),
+            PropUnit => PropertyPayload::Unit(rdr.read_u32::<BigEndian>()?),
+            PropParasites => {
+                let mut data = vec![0; length];
+                rdr.read_exact(&mut data)?;
+                match ParasiteProperty::parse_list(&data) {
+                    Some(parasites) => PropertyPayload::Parasites(parasites),
+                    // binary parasites can't be represented, keep them as they are
+                    None => PropertyPayload::Unknown(data),
+                }
+            }
+            TypeIdentification => {
+                let mut guides = Vec::with_capacity(length / 5);
+                for _ in 0..length / 5 {
+                    guides.push(GuideProperty {
+                        position: rdr.read_i32::<BigEndian>()?,
+                        orientation: rdr.read_u8()?,
+                    });
+                }
+                PropertyPayload::Guides(guides)
+            }
+            PropFloatingSelection => {
+                PropertyPayload::FloatingSelection(rdr.read_uint::<BigEndian>(length.min(8))?)
+            }
+            PropActiveLayer => PropertyPayload::ActiveLayer(),
+            PropActiveChannel => PropertyPayload::ActiveChannel(),
+            PropSelection => PropertyPayload::SelectionChannel(),
+            PropOpacity => {
+                let mut opacity = [0; 4];
+                rdr.read_exact(&mut opacity)?;
+                PropertyPayload::OpacityLayer(RgbaPixel(opacity))
+            }
+            PropFloatOpacity => PropertyPayload::FloatOpacityLayer(rdr.read_f32::<BigEndian>()?),
+            PropVisible => PropertyPayload::VisibleLayer(rdr.read_u32::<BigEndian>()?),
+            PropLinked => PropertyPayload::LinkedLayer(rdr.read_u32::<BigEndian>()?),
+            PropColorTag => PropertyPayload::ColorTagLayer(rdr.read_u32::<BigEndian>()?),
+            PropLockContent => PropertyPayload::LockContentLayer(rdr.read_u32::<BigEndian>()?),
+            PropLockAlpha => PropertyPayload::LockAlphaLayer(rdr.read_u32::<BigEndian>()?),
+            PropLockPosition => PropertyPayload::LockPositionLayer(rdr.read_u32::<BigEndian>()?),
+            PropLockVisibility => {
+                PropertyPayload::LockVisibilityLayer(rdr.read_u32::<BigEndian>()?)
+            }
+            PropTextLayerFlags => {
+                PropertyPayload::TextLayerFlagsLayer(rdr.read_u32::<BigEndian>()?)
+            }
+            PropOffsets => PropertyPayload::OffsetsLayer(
+                rdr.read_i32::<BigEndian>()?,
+                rdr.read_i32::<BigEndian>()?,
+            ),
+            PropMode => PropertyPayload::ModeLayer(rdr.read_u32::<BigEndian>()?),
+            PropBlendSpace => PropertyPayload::BlendSpaceLayer(rdr.read_u32::<BigEndian>()?),
+            PropCompositeSpace => {
+                PropertyPayload::CompositeSpaceLayer(rdr.read_u32::<BigEndian>()?)
+            }
+            PropCompositeMode => PropertyPayload::CompositeModeLayer(rdr.read_u32::<BigEndian>()?),
+            PropApplyMask => PropertyPayload::ApplyMaskLayer(rdr.read_u32::<BigEndian>()?),
+            PropEditMask => PropertyPayload::EditMaskLayer(rdr.read_u32::<BigEndian>()?),
+            PropShowMask => PropertyPayload::ShowMaskLayer(rdr.read_u32::<BigEndian>()?),
+            PropTattoo => PropertyPayload::Tatoo(rdr.read_u32::<BigEndian>()?),
+            PropShowMasked => PropertyPayload::ShowMaskedChannel(rdr.read_u32::<BigEndian>()?),
+            PropGroupItem => PropertyPayload::GroupItemLayer(),
+            PropItemPath => {
+                let mut path = Vec::with_capacity(length / 4);
+                for _ in 0..length / 4 {
+                    path.push(rdr.read_u32::<BigEndian>()?);
+                }
+                PropertyPayload::ItemPathLayer(path)
+            }
+            PropGroupItemFlags => {
+                PropertyPayload::GroupItemFlagsLayer(rdr.read_u32::<BigEndian>()?)
+            }
+            PropColor => {
+                let mut color = [0; 3];
+                rdr.read_exact(&mut color)?;
+                PropertyPayload::ColorChannel(color)
+            }
+            PropFloatColor => PropertyPayload::FloatColorChannel([
+                rdr.read_f32::<BigEndian>()?,
+                rdr.read_f32::<BigEndian>()?,
+                rdr.read_f32::<BigEndian>()?,
+            ]),
             _ => {
                 let mut p = vec![0; length];
                 rdr.read_exact(&mut p)?;
@@ -102,39 +318,579 @@ impl PropertyPayload {
     }
 }
 
-impl Layer {
-    fn parse<R: Read +  Seek + std::fmt::Debug>(mut rdr: R, version: Version) -> Result<Layer, Error> {
+impl ParasiteProperty {
+    /// Parse the parasites of a property, `None` when the data of one of them isn't a
+    /// string.
+    fn parse_list(mut data: &[u8]) -> Option<Vec<ParasiteProperty>> {
+        let mut parasites = vec![];
+        while !data.is_empty() {
+            let name = read_gimp_string(&mut data).ok()?;
+            let flags = data.read_u32::<BigEndian>().ok()?;
+            let length = data.read_u32::<BigEndian>().ok()? as usize;
+            if length == 0 || length > data.len() {
+                return None;
+            }
+            let (value, rest) = data.split_at(length);
+            let value = value.strip_suffix(&[0])?;
+            if value.contains(&0) {
+                return None;
+            }
+            parasites.push(ParasiteProperty {
+                name,
+                flags,
+                data: String::from_utf8(value.to_vec()).ok()?,
+            });
+            data = rest;
+        }
+        Some(parasites)
+    }
+}
+
+impl LayerHandle {
+    fn parse<R: Read + Seek + std::fmt::Debug>(
+        mut rdr: R,
+        header: &XcfHeader,
+    ) -> Result<LayerHandle, Error> {
+        let version = header.version;
         let width = rdr.read_u32::<BigEndian>()?;
         let height = rdr.read_u32::<BigEndian>()?;
         let kind = LayerColorType::new(rdr.read_u32::<BigEndian>()?)?;
         let name = read_gimp_string(&mut rdr)?;
-        let properties = Property::parse_list(&mut rdr)?;
+        let properties = Property::parse_list(&mut rdr, version)?;
         let hptr = rdr.read_uint::<BigEndian>(version.bytes_per_offset())?;
-        let current_pos = rdr.stream_position()?;
-        rdr.seek(SeekFrom::Start(hptr))?;
-        let pixels = PixelData::parse_hierarchy(&mut rdr, version)?;
-        rdr.seek(SeekFrom::Start(current_pos))?;
-        // TODO
-        // let mptr = rdr.read_uint::<BigEndian>(version.bytes_per_offset())?;
-        Ok(Layer {
+        let mptr = rdr.read_uint::<BigEndian>(version.bytes_per_offset())?;
+        let hierarchy = if properties
+            .iter()
+            .any(|p| p.kind == PropertyIdentifier::PropGroupItem)
+        {
+            // the hierarchy of a group is a render of its children, GIMP ignores it as well
+            None
+        } else {
+            rdr.seek(SeekFrom::Start(hptr))?;
+            let hierarchy = Hierarchy::parse(&mut rdr, version)?;
+            // the size of a pixel is given by the type of the layer and the precision of the image
+            // and its size must be the size of the layer
+            if hierarchy.bpp != kind.kind.channels() * header.precision.bytes_per_component()
+                || (hierarchy.width, hierarchy.height) != (width, height)
+            {
+                return Err(Error::InvalidFormat);
+            }
+            Some(hierarchy)
+        };
+        let mask = if mptr != 0 {
+            rdr.seek(SeekFrom::Start(mptr))?;
+            Some(ChannelHandle::parse(&mut rdr, header)?)
+        } else {
+            None
+        };
+        Ok(LayerHandle {
             width,
             height,
             kind,
             name,
             properties,
+            hierarchy,
+            mask,
+            layer: OnceCell::new(),
+        })
+    }
+
+    /// Decode the pixels of the layer and its mask.
+    fn decode<R: Read + Seek + std::fmt::Debug>(
+        &self,
+        rdr: R,
+        header: &XcfHeader,
+    ) -> Result<Layer, Error> {
+        let tiles = self.read_tiles(rdr, header)?;
+        self.build(tiles, header)
+    }
+
+    /// Read the tiles of the layer and its mask, to be decoded by [`LayerHandle::build`].
+    fn read_tiles<R: Read + Seek>(
+        &self,
+        mut rdr: R,
+        header: &XcfHeader,
+    ) -> Result<LayerTiles, Error> {
+        let tiles = match &self.hierarchy {
+            Some(hierarchy) => {
+                Some(hierarchy.read_tiles(&mut rdr, header.compression(), hierarchy.bounds())?)
+            }
+            None => None,
+        };
+        let mask = match &self.mask {
+            Some(mask) => Some(mask.read_tiles(&mut rdr, header)?),
+            None => None,
+        };
+        Ok(LayerTiles { tiles, mask })
+    }
+
+    fn build(&self, tiles: LayerTiles, header: &XcfHeader) -> Result<Layer, Error> {
+        let (pixels, native) = match (&self.hierarchy, tiles.tiles) {
+            (Some(hierarchy), Some(tiles)) => {
+                let native = hierarchy.assemble(&tiles, header.precision, hierarchy.bounds())?;
+                (
+                    native.to_rgba(&self.kind.kind, header.palette()),
+                    Some(native),
+                )
+            }
+            _ => {
+                let pixels = PixelData {
+                    width: 0,
+                    height: 0,
+                    pixels: vec![],
+                };
+                (pixels, None)
+            }
+        };
+        let mask = match (&self.mask, tiles.mask) {
+            (Some(mask), Some(tiles)) => Some(mask.build(&tiles, header)?),
+            _ => None,
+        };
+        Ok(Layer {
+            width: self.width,
+            height: self.height,
+            kind: self.kind.clone(),
+            name: self.name.clone(),
+            properties: self.properties.clone(),
             pixels,
+            native,
+            mask,
         })
     }
+}
+
+/// The tiles of a layer and of its mask, read but not decoded yet.
+#[derive(Default)]
+struct LayerTiles {
+    tiles: Option<Vec<Tile>>,
+    mask: Option<Vec<Tile>>,
+}
+
+/// Get the value of a flag property of a layer or channel, `None` when it has none.
+fn flag(properties: &[Property], kind: PropertyIdentifier) -> Option<bool> {
+    properties
+        .iter()
+        .filter(|p| p.kind == kind)
+        .find_map(|p| match p.payload {
+            PropertyPayload::VisibleLayer(value)
+            | PropertyPayload::LinkedLayer(value)
+            | PropertyPayload::LockContentLayer(value)
+            | PropertyPayload::LockAlphaLayer(value)
+            | PropertyPayload::LockPositionLayer(value)
+            | PropertyPayload::LockVisibilityLayer(value)
+            | PropertyPayload::ApplyMaskLayer(value)
+            | PropertyPayload::EditMaskLayer(value)
+            | PropertyPayload::ShowMaskLayer(value)
+            | PropertyPayload::ShowMaskedChannel(value) => Some(value != 0),
+            _ => None,
+        })
+}
+
+/// Get the opacity of a layer or channel, the float property winning over the 8-bit one.
+fn opacity(properties: &[Property]) -> f32 {
+    let mut opacity = 1.0;
+    for property in properties {
+        match &property.payload {
+            PropertyPayload::FloatOpacityLayer(value) => return *value,
+            PropertyPayload::OpacityLayer(value) => {
+                opacity = u32::from_be_bytes(value.0) as f32 / 255.0
+            }
+            _ => {}
+        }
+    }
+    opacity
+}
+
+impl Layer {
+    /// Get the channels of a pixel as they are stored in the file, e.g. `[gray]` or
+    /// `[gray, alpha]` for a grayscale layer.
+    ///
+    /// Only available for layers that were read from a file.
+    pub fn native_pixel(&self, x: u32, y: u32) -> Option<&[u8]> {
+        self.native.as_ref()?.pixel(x, y)
+    }
 
     pub fn pixel(&self, x: u32, y: u32) -> Option<RgbaPixel> {
         self.pixels.pixel(x, y)
     }
 
+    /// Get the channels of all the pixels at the precision of the image, row by row, the
+    /// channels of a pixel following each other.
+    ///
+    /// Unlike [`Layer::pixel`], which is limited to 8 bits per channel, this keeps the full
+    /// precision of 16 and 32-bit or floating point images. Only available for layers that were
+    /// read from a file.
+    pub fn components(&self) -> Option<Components> {
+        Some(self.native.as_ref()?.components())
+    }
+
+    /// Get the index in the palette of the image of a pixel of an indexed layer.
+    pub fn palette_index(&self, x: u32, y: u32) -> Option<u8> {
+        match self.kind.kind {
+            LayerColorValue::Indexed | LayerColorValue::IndexedWithAlpha => {
+                Some(self.native_pixel(x, y)?[0])
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the palette indices of all the pixels of an indexed layer, row by row.
+    pub fn raw_index_buffer(&self) -> Option<Vec<u8>> {
+        let native = self.native.as_ref()?;
+        match self.kind.kind {
+            LayerColorValue::Indexed | LayerColorValue::IndexedWithAlpha => Some(
+                native
+                    .data
+                    .iter()
+                    .step_by(native.bpp as usize)
+                    .copied()
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Get a pixel with the layer mask applied to its alpha, when the mask is enabled.
+    pub fn masked_pixel(&self, x: u32, y: u32) -> Option<RgbaPixel> {
+        let mut pixel = self.pixel(x, y)?;
+        if let (Some(mask), true) = (&self.mask, self.apply_mask()) {
+            let value = u32::from(mask.pixel(x, y).unwrap_or(255));
+            pixel.0[3] = ((u32::from(pixel.a()) * value + 127) / 255) as u8;
+        }
+        Some(pixel)
+    }
+
+    /// Replace the property of the same kind as `property`, or add it when the layer has none.
+    pub fn set_property(&mut self, property: Property) {
+        match self.properties.iter_mut().find(|p| p.kind == property.kind) {
+            Some(existing) => *existing = property,
+            None => self.properties.push(property),
+        }
+    }
+
+    fn set_flag(
+        &mut self,
+        kind: PropertyIdentifier,
+        payload: fn(u32) -> PropertyPayload,
+        value: bool,
+    ) {
+        self.set_property(Property {
+            kind,
+            length: 4,
+            payload: payload(u32::from(value)),
+        });
+    }
+
+    /// Get the opacity of the layer, between 0.0 and 1.0.
+    pub fn opacity(&self) -> f32 {
+        opacity(&self.properties)
+    }
+
+    /// Set the opacity of the layer, both as a float and in the 8-bit property older versions
+    /// of GIMP read.
+    pub fn set_opacity(&mut self, opacity: f32) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        let legacy = (opacity * 255.0).round() as u32;
+        self.set_property(Property {
+            kind: PropertyIdentifier::PropOpacity,
+            length: 4,
+            payload: PropertyPayload::OpacityLayer(RgbaPixel(legacy.to_be_bytes())),
+        });
+        self.set_property(Property {
+            kind: PropertyIdentifier::PropFloatOpacity,
+            length: 4,
+            payload: PropertyPayload::FloatOpacityLayer(opacity),
+        });
+    }
+
+    /// Whether the layer is visible, layers without visibility property are.
+    pub fn is_visible(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropVisible).unwrap_or(true)
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.set_flag(
+            PropertyIdentifier::PropVisible,
+            PropertyPayload::VisibleLayer,
+            visible,
+        );
+    }
+
+    /// Whether the layer is linked to other layers, to move them together.
+    pub fn is_linked(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropLinked).unwrap_or(false)
+    }
+
+    pub fn set_linked(&mut self, linked: bool) {
+        self.set_flag(
+            PropertyIdentifier::PropLinked,
+            PropertyPayload::LinkedLayer,
+            linked,
+        );
+    }
+
+    /// Get the position of the top left corner of the layer on the canvas.
+    pub fn offsets(&self) -> (i32, i32) {
+        self.properties
+            .iter()
+            .find_map(|p| match p.payload {
+                PropertyPayload::OffsetsLayer(x, y) => Some((x, y)),
+                _ => None,
+            })
+            .unwrap_or((0, 0))
+    }
+
+    pub fn set_offsets(&mut self, x: i32, y: i32) {
+        self.set_property(Property {
+            kind: PropertyIdentifier::PropOffsets,
+            length: 8,
+            payload: PropertyPayload::OffsetsLayer(x, y),
+        });
+    }
+
+    /// Get the area covered by the layer on the canvas, it may extend past the canvas.
+    pub fn bounds(&self) -> Rect {
+        let (x, y) = self.offsets();
+        Rect::new(x, y, self.width, self.height)
+    }
+
+    /// Get a pixel by its position on the canvas rather than in the layer.
+    pub fn canvas_pixel(&self, x: i32, y: i32) -> Option<RgbaPixel> {
+        let bounds = self.bounds();
+        if !bounds.contains(x, y) {
+            return None;
+        }
+        self.pixel((x - bounds.x) as u32, (y - bounds.y) as u32)
+    }
+
+    /// Get the blend mode of the layer, [`LayerMode::Normal`] when it is missing or unknown.
+    pub fn mode(&self) -> LayerMode {
+        self.properties
+            .iter()
+            .find_map(|p| match p.payload {
+                PropertyPayload::ModeLayer(mode) => LayerMode::new(mode).ok(),
+                _ => None,
+            })
+            .unwrap_or(LayerMode::Normal)
+    }
+
+    pub fn set_mode(&mut self, mode: LayerMode) {
+        self.set_property(Property {
+            kind: PropertyIdentifier::PropMode,
+            length: 4,
+            payload: PropertyPayload::ModeLayer(mode as u32),
+        });
+    }
+
+    /// Get the color space the layer is blended in, "auto" when GIMP picks it from the mode.
+    pub fn blend_space(&self) -> LayerColorSpace {
+        self.properties
+            .iter()
+            .find_map(|p| match p.payload {
+                PropertyPayload::BlendSpaceLayer(space) => LayerColorSpace::new(space).ok(),
+                _ => None,
+            })
+            .unwrap_or(LayerColorSpace::Auto)
+    }
+
+    pub fn set_blend_space(&mut self, space: LayerColorSpace) {
+        self.set_property(Property {
+            kind: PropertyIdentifier::PropBlendSpace,
+            length: 4,
+            payload: PropertyPayload::BlendSpaceLayer(space as u32),
+        });
+    }
+
+    /// Get the color space the layer is composited in, "auto" when GIMP picks it from the mode.
+    pub fn composite_space(&self) -> LayerColorSpace {
+        self.properties
+            .iter()
+            .find_map(|p| match p.payload {
+                PropertyPayload::CompositeSpaceLayer(space) => LayerColorSpace::new(space).ok(),
+                _ => None,
+            })
+            .unwrap_or(LayerColorSpace::Auto)
+    }
+
+    pub fn set_composite_space(&mut self, space: LayerColorSpace) {
+        self.set_property(Property {
+            kind: PropertyIdentifier::PropCompositeSpace,
+            length: 4,
+            payload: PropertyPayload::CompositeSpaceLayer(space as u32),
+        });
+    }
+
+    /// Get how the layer is composited with its backdrop, "auto" when GIMP picks it from the
+    /// mode.
+    pub fn composite_mode(&self) -> LayerCompositeMode {
+        self.properties
+            .iter()
+            .find_map(|p| match p.payload {
+                PropertyPayload::CompositeModeLayer(mode) => LayerCompositeMode::new(mode).ok(),
+                _ => None,
+            })
+            .unwrap_or(LayerCompositeMode::Auto)
+    }
+
+    pub fn set_composite_mode(&mut self, mode: LayerCompositeMode) {
+        self.set_property(Property {
+            kind: PropertyIdentifier::PropCompositeMode,
+            length: 4,
+            payload: PropertyPayload::CompositeModeLayer(mode as u32),
+        });
+    }
+
+    /// Get how the layer is composited, with the "auto" spaces and composite mode resolved for
+    /// its mode like GIMP does.
+    pub fn compositing(&self) -> Compositing {
+        let mode = self.mode();
+        Compositing {
+            mode,
+            blend_space: self.blend_space().or(mode.blend_space()),
+            composite_space: self.composite_space().or(mode.composite_space()),
+            composite_mode: self.composite_mode().or(mode.composite_mode()),
+        }
+    }
+
+    /// Get the color tag of the layer in the layers dialog of GIMP.
+    pub fn color_tag(&self) -> ColorTag {
+        self.properties
+            .iter()
+            .find_map(|p| match p.payload {
+                PropertyPayload::ColorTagLayer(tag) => ColorTag::new(tag).ok(),
+                _ => None,
+            })
+            .unwrap_or(ColorTag::None)
+    }
+
+    pub fn set_color_tag(&mut self, tag: ColorTag) {
+        self.set_property(Property {
+            kind: PropertyIdentifier::PropColorTag,
+            length: 4,
+            payload: PropertyPayload::ColorTagLayer(tag as u32),
+        });
+    }
+
+    /// Whether the pixels of the layer are locked.
+    pub fn lock_content(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropLockContent).unwrap_or(false)
+    }
+
+    pub fn set_lock_content(&mut self, lock: bool) {
+        self.set_flag(
+            PropertyIdentifier::PropLockContent,
+            PropertyPayload::LockContentLayer,
+            lock,
+        );
+    }
+
+    /// Whether the alpha channel of the layer is locked.
+    pub fn lock_alpha(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropLockAlpha).unwrap_or(false)
+    }
+
+    pub fn set_lock_alpha(&mut self, lock: bool) {
+        self.set_flag(
+            PropertyIdentifier::PropLockAlpha,
+            PropertyPayload::LockAlphaLayer,
+            lock,
+        );
+    }
+
+    /// Whether the position of the layer is locked.
+    pub fn lock_position(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropLockPosition).unwrap_or(false)
+    }
+
+    pub fn set_lock_position(&mut self, lock: bool) {
+        self.set_flag(
+            PropertyIdentifier::PropLockPosition,
+            PropertyPayload::LockPositionLayer,
+            lock,
+        );
+    }
+
+    /// Whether the visibility of the layer is locked.
+    pub fn lock_visibility(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropLockVisibility).unwrap_or(false)
+    }
+
+    pub fn set_lock_visibility(&mut self, lock: bool) {
+        self.set_flag(
+            PropertyIdentifier::PropLockVisibility,
+            PropertyPayload::LockVisibilityLayer,
+            lock,
+        );
+    }
+
+    /// Whether the layer mask is applied to the layer.
+    pub fn apply_mask(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropApplyMask).unwrap_or(false)
+    }
+
+    pub fn set_apply_mask(&mut self, apply: bool) {
+        self.set_flag(
+            PropertyIdentifier::PropApplyMask,
+            PropertyPayload::ApplyMaskLayer,
+            apply,
+        );
+    }
+
+    /// Whether the layer mask is being edited in GIMP instead of the layer.
+    pub fn edit_mask(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropEditMask).unwrap_or(false)
+    }
+
+    pub fn set_edit_mask(&mut self, edit: bool) {
+        self.set_flag(
+            PropertyIdentifier::PropEditMask,
+            PropertyPayload::EditMaskLayer,
+            edit,
+        );
+    }
+
+    /// Whether GIMP displays the layer mask instead of the layer.
+    pub fn show_mask(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropShowMask).unwrap_or(false)
+    }
+
+    pub fn set_show_mask(&mut self, show: bool) {
+        self.set_flag(
+            PropertyIdentifier::PropShowMask,
+            PropertyPayload::ShowMaskLayer,
+            show,
+        );
+    }
+
+    /// Whether the layer is a group of layers, groups have no pixels of their own.
+    pub fn is_group(&self) -> bool {
+        self.properties
+            .iter()
+            .any(|p| p.kind == PropertyIdentifier::PropGroupItem)
+    }
+
+    /// Whether the group is expanded in the layers dialog of GIMP.
+    pub fn is_expanded(&self) -> bool {
+        self.properties.iter().any(|p| match p.payload {
+            PropertyPayload::GroupItemFlagsLayer(flags) => flags & 1 != 0,
+            _ => false,
+        })
+    }
+
+    /// Get the position of the layer in each of its parent groups, the last one being its
+    /// position in its own group. `None` for top-level layers.
+    pub fn item_path(&self) -> Option<&[u32]> {
+        self.properties.iter().find_map(|p| match &p.payload {
+            PropertyPayload::ItemPathLayer(path) => Some(&path[..]),
+            _ => None,
+        })
+    }
+
     pub fn dimensions(&self) -> (u32, u32) {
         (self.width, self.height)
     }
 
-    pub fn raw_rgba_buffer(&self) -> Cow<[RgbaPixel]> {
+    pub fn raw_rgba_buffer(&self) -> Cow<'_, [RgbaPixel]> {
         Cow::from(&self.pixels.pixels)
     }
 
@@ -143,6 +899,110 @@ impl Layer {
     }
 }
 
+impl ChannelHandle {
+    fn parse<R: Read + Seek + std::fmt::Debug>(
+        mut rdr: R,
+        header: &XcfHeader,
+    ) -> Result<ChannelHandle, Error> {
+        let version = header.version;
+        let width = rdr.read_u32::<BigEndian>()?;
+        let height = rdr.read_u32::<BigEndian>()?;
+        let name = read_gimp_string(&mut rdr)?;
+        let properties = Property::parse_list(&mut rdr, version)?;
+        let hptr = rdr.read_uint::<BigEndian>(version.bytes_per_offset())?;
+        rdr.seek(SeekFrom::Start(hptr))?;
+        let hierarchy = Hierarchy::parse(&mut rdr, version)?;
+        if hierarchy.bpp != header.precision.bytes_per_component()
+            || (hierarchy.width, hierarchy.height) != (width, height)
+        {
+            return Err(Error::InvalidFormat);
+        }
+        Ok(ChannelHandle {
+            width,
+            height,
+            name,
+            properties,
+            hierarchy,
+            channel: OnceCell::new(),
+        })
+    }
+
+    /// Decode the pixels of the channel.
+    fn decode<R: Read + Seek + std::fmt::Debug>(
+        &self,
+        rdr: R,
+        header: &XcfHeader,
+    ) -> Result<Channel, Error> {
+        let tiles = self.read_tiles(rdr, header)?;
+        self.build(&tiles, header)
+    }
+
+    fn read_tiles<R: Read + Seek>(&self, rdr: R, header: &XcfHeader) -> Result<Vec<Tile>, Error> {
+        self.hierarchy
+            .read_tiles(rdr, header.compression(), self.hierarchy.bounds())
+    }
+
+    fn build(&self, tiles: &[Tile], header: &XcfHeader) -> Result<Channel, Error> {
+        let native = self
+            .hierarchy
+            .assemble(tiles, header.precision, self.hierarchy.bounds())?;
+        Ok(Channel {
+            width: self.width,
+            height: self.height,
+            name: self.name.clone(),
+            properties: self.properties.clone(),
+            pixels: native.to_u8(),
+            native: Some(native),
+        })
+    }
+}
+
+impl Channel {
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u8> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.pixels[(y * self.width + x) as usize])
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Get the opacity of the channel, between 0.0 and 1.0.
+    pub fn opacity(&self) -> f32 {
+        opacity(&self.properties)
+    }
+
+    /// Whether the channel is visible, channels without visibility property are.
+    pub fn is_visible(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropVisible).unwrap_or(true)
+    }
+
+    /// Get the color used to display the channel in GIMP.
+    pub fn color(&self) -> [u8; 3] {
+        self.properties
+            .iter()
+            .find_map(|p| match p.payload {
+                PropertyPayload::ColorChannel(color) => Some(color),
+                _ => None,
+            })
+            .unwrap_or([0, 0, 0])
+    }
+
+    /// Whether GIMP shows the masked area of the channel (rather than the selected area).
+    pub fn show_masked(&self) -> bool {
+        flag(&self.properties, PropertyIdentifier::PropShowMasked).unwrap_or(false)
+    }
+
+    pub fn tattoo(&self) -> Option<u32> {
+        self.properties.iter().find_map(|p| match p.payload {
+            PropertyPayload::Tatoo(value) => Some(value),
+            _ => None,
+        })
+    }
+}
+
 #[repr(u32)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum LayerColorValue {
@@ -174,9 +1034,19 @@ impl LayerColorValue {
             LayerColorValue::Rgb | LayerColorValue::Grayscale | LayerColorValue::Indexed => false
         }
     }
+
+    /// Number of channels stored for each pixel, including alpha.
+    pub fn channels(&self) -> u32 {
+        match self {
+            LayerColorValue::Grayscale | LayerColorValue::Indexed => 1,
+            LayerColorValue::GrayscaleWithAlpha | LayerColorValue::IndexedWithAlpha => 2,
+            LayerColorValue::Rgb => 3,
+            LayerColorValue::Rgba => 4,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct LayerColorType {
     pub kind: LayerColorValue,
     pub alpha: bool,
@@ -184,95 +1054,18 @@ pub struct LayerColorType {
 
 impl LayerColorType {
     fn new(identifier: u32) -> Result<LayerColorType, Error> {
-        let kind = LayerColorValue::new(identifier / 2)?;
-        let alpha = identifier % 2 == 1;
+        let kind = LayerColorValue::new(identifier)?;
+        let alpha = LayerColorValue::has_alpha(kind.clone());
         Ok(LayerColorType { alpha, kind })
     }
 }
 
-pub struct TileCursor {
-    width: u32,
-    height: u32,
-    channels: u32,
-    x: u32,
-    y: u32,
-    i: u32,
-}
-
-// TODO: I like the use of a struct but this isn't really any kind of cursor.
-// The use of a struct allows us to seperate the state we need to refer to from the number of
-// stuff we need to store within the "algorithm." A better design is very welcome! i should be
-// moved into feed as a local.
-impl TileCursor {
-    fn new(width: u32, height: u32, tx: u32, ty: u32, channels: u32) -> TileCursor {
-        TileCursor {
-            width,
-            height,
-            channels,
-            x: tx * 64,
-            y: ty * 64,
-            i: 0,
-        }
-    }
-
-    /// Feed the cursor a stream starting at the beginning of an XCF tile structure.
-    fn feed<R: Read + Seek + std::fmt::Debug>(&mut self, mut rdr: R, pixels: &mut [RgbaPixel]) -> Result<(), Error> {
-        let twidth = cmp::min(self.x + 64, self.width) - self.x;
-        let theight = cmp::min(self.y + 64, self.height) - self.y;
-        let base_offset = self.y * self.width + self.x;
-        // each channel is laid out one after the other
-        let mut channel = 0;
-        while channel < self.channels {
-            while self.i < twidth * theight {
-                let determinant = rdr.read_u8()?;
-                if determinant < 127 {
-                    // A short run of identical bytes
-                    let run = u32::from(determinant + 1);
-                    let v = rdr.read_u8()?;
-                    for i in (self.i)..(self.i + run) {
-                        let index = base_offset + (i / twidth) * self.width + i % twidth;
-                        pixels[index as usize].0[channel as usize] = v;
-                    }
-                    self.i += run;
-                } else if determinant == 127 {
-                    // A long run of identical bytes
-                    let run = u32::from(rdr.read_u16::<BigEndian>()?);
-                    let v = rdr.read_u8()?;
-                    for i in (self.i)..(self.i + run) {
-                        let index = base_offset + (i / twidth) * self.width + i % twidth;
-                        pixels[index as usize].0[channel as usize] = v;
-                    }
-                    self.i += run;
-                } else if determinant == 128 {
-                    // A long run of different bytes
-                    let stream_run = u32::from(rdr.read_u16::<BigEndian>()?);
-                    for i in (self.i)..(self.i + stream_run) {
-                        let index = base_offset + (i / twidth) * self.width + i % twidth;
-                        let v = rdr.read_u8()?;
-                        pixels[index as usize].0[channel as usize] = v;
-                    }
-                    self.i += stream_run;
-                } else {
-                    // A short run of different bytes
-                    let stream_run = 256 - u32::from(determinant);
-                    for i in (self.i)..(self.i + stream_run) {
-                        let index = base_offset + (i / twidth) * self.width + i % twidth;
-                        let v = rdr.read_u8()?;
-                        pixels[index as usize].0[channel as usize] = v;
-                    }
-                    self.i += stream_run;
-                }
-            }
-
-            self.i = 0;
-            channel += 1;
-        }
-        Ok(())
-    }
-}
-
 fn read_gimp_string<R: Read>(mut rdr: R) -> Result<String, Error> {
     let length = rdr.read_u32::<BigEndian>()?;
+    if length == 0 {
+        // a null string
+        return Ok(String::new());
+    }
     let mut buffer = vec![0; length as usize - 1];
     rdr.read_exact(&mut buffer)?;
     // read the DUMB trailing null byte... uhh GIMP team RIIR already? ;p
@@ -291,7 +1084,22 @@ impl Xcf {
     }
 
     /// Read an XCF file from a Reader.
-    pub fn load<R: Read + Seek + std::fmt::Debug>(mut rdr: R) -> Result<Xcf, Error> {
+    pub fn load<R: Read + Seek + std::fmt::Debug>(rdr: R) -> Result<Xcf, Error> {
+        Xcf::load_lazy(rdr)?.into_xcf()
+    }
+
+    /// Open an XCF file at the path specified, without decoding its pixels, see
+    /// [`Xcf::load_lazy`].
+    pub fn open_lazy<P: AsRef<Path>>(p: P) -> Result<LazyXcf<BufReader<File>>, Error> {
+        let rdr = BufReader::new(File::open(p)?);
+        Xcf::load_lazy(rdr)
+    }
+
+    /// Read the header, the properties and the tile pointers of the layers and channels of an
+    /// XCF file, their pixels are only decoded when they are asked for.
+    ///
+    /// This is much faster than [`Xcf::load`] to list layers or to get a few of them.
+    pub fn load_lazy<R: Read + Seek + std::fmt::Debug>(mut rdr: R) -> Result<LazyXcf<R>, Error> {
         let header = XcfHeader::parse(&mut rdr)?;
 
         let mut layers = Vec::new();
@@ -302,13 +1110,28 @@ impl Xcf {
             }
             let current_pos = rdr.stream_position()?;
             rdr.seek(SeekFrom::Start(layer_pointer))?;
-            layers.push(Layer::parse(&mut rdr, header.version)?);
+            layers.push(LayerHandle::parse(&mut rdr, &header)?);
             rdr.seek(SeekFrom::Start(current_pos))?;
         }
 
-        // TODO: Read channels
+        let mut channels = Vec::new();
+        loop {
+            let channel_pointer = rdr.read_uint::<BigEndian>(header.version.bytes_per_offset())?;
+            if channel_pointer == 0 {
+                break;
+            }
+            let current_pos = rdr.stream_position()?;
+            rdr.seek(SeekFrom::Start(channel_pointer))?;
+            channels.push(ChannelHandle::parse(&mut rdr, &header)?);
+            rdr.seek(SeekFrom::Start(current_pos))?;
+        }
 
-        Ok(Xcf { header, layers })
+        Ok(LazyXcf {
+            header,
+            layers,
+            channels,
+            reader: RefCell::new(rdr),
+        })
     }
 
     /// Get the width of the canvas.
@@ -335,9 +1158,302 @@ impl Xcf {
     pub fn layer_mut(&mut self, name: &str) -> Option<&mut Layer> {
         self.layers.iter_mut().find(|l| l.name == name)
     }
+
+    /// Get the colormap of an indexed image.
+    pub fn palette(&self) -> Option<&Palette> {
+        self.header.palette()
+    }
+
+    /// Get the area of the canvas.
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width(), self.height())
+    }
+
+    /// Get the part of a layer which is on the canvas, in canvas coordinates. `None` when the
+    /// layer lies entirely outside of it.
+    pub fn layer_bounds_on_canvas(&self, layer: &Layer) -> Option<Rect> {
+        layer.bounds().intersection(&self.bounds())
+    }
+
+    /// Rebuild the tree of layer groups, returns the top-level layers from top to bottom.
+    ///
+    /// A layer whose item path leads to a group that doesn't come before it in the file is put
+    /// at the top level, after the layers already there.
+    pub fn layer_tree(&self) -> Vec<LayerNode<'_>> {
+        let mut roots: Vec<LayerNode> = vec![];
+        for (index, layer) in self.layers.iter().enumerate() {
+            // groups are stored before their children, and the children in order
+            let mut parents = match layer.item_path() {
+                Some([parents @ .., _]) => parents,
+                _ => &[],
+            };
+            let found = parents.iter().try_fold(&roots[..], |siblings, &position| {
+                siblings
+                    .get(position as usize)
+                    .map(|node| &node.children[..])
+            });
+            if found.is_none() {
+                parents = &[];
+            }
+            let mut siblings = &mut roots;
+            for &position in parents {
+                siblings = &mut siblings[position as usize].children;
+            }
+            siblings.push(LayerNode {
+                layer,
+                index,
+                children: vec![],
+            });
+        }
+        roots
+    }
+
+    /// Get a reference to a layer by the names of its groups and its own, separated by `/`,
+    /// e.g. `"Characters/Hero/Arm"`.
+    pub fn layer_by_path(&self, path: &str) -> Option<&Layer> {
+        let index = LayerNode::find_in(&self.layer_tree(), path)?.index;
+        Some(&self.layers[index])
+    }
+
+    /// Get a mutable reference to a layer by path, see [`Xcf::layer_by_path`].
+    pub fn layer_by_path_mut(&mut self, path: &str) -> Option<&mut Layer> {
+        let index = LayerNode::find_in(&self.layer_tree(), path)?.index;
+        Some(&mut self.layers[index])
+    }
+
+    /// Get a reference to a channel by `name`.
+    pub fn channel(&self, name: &str) -> Option<&Channel> {
+        self.channels.iter().find(|c| c.name == name)
+    }
+
+    /// Get a mutable reference to a channel by `name`.
+    pub fn channel_mut(&mut self, name: &str) -> Option<&mut Channel> {
+        self.channels.iter_mut().find(|c| c.name == name)
+    }
+}
+
+impl<R: Read + Seek + std::fmt::Debug> LazyXcf<R> {
+    /// Get the width of the canvas.
+    pub fn width(&self) -> u32 {
+        self.header.width
+    }
+
+    /// Get the height of the canvas.
+    pub fn height(&self) -> u32 {
+        self.header.height
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width(), self.height())
+    }
+
+    /// Get the handle of a layer by name.
+    pub fn layer(&self, name: &str) -> Option<&LayerHandle> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    /// Get the handle of a channel by name.
+    pub fn channel(&self, name: &str) -> Option<&ChannelHandle> {
+        self.channels.iter().find(|c| c.name == name)
+    }
+
+    /// Decode the pixels of a layer of this file and its mask, they are kept for the next
+    /// calls.
+    pub fn load_layer<'h>(&self, handle: &'h LayerHandle) -> Result<&'h Layer, Error> {
+        if let Some(layer) = handle.layer.get() {
+            return Ok(layer);
+        }
+        let layer = handle.decode(&mut *self.reader.borrow_mut(), &self.header)?;
+        Ok(handle.layer.get_or_init(|| layer))
+    }
+
+    /// Decode the pixels of a part of a layer of this file, in the coordinates of the layer.
+    ///
+    /// Only the tiles which intersect `region` are read, the returned pixels have the size of
+    /// `region` and the parts of it outside of the layer are transparent.
+    pub fn layer_region(&self, handle: &LayerHandle, region: Rect) -> Result<PixelData, Error> {
+        let mut pixels = match &handle.hierarchy {
+            Some(hierarchy) => hierarchy
+                .decode_region(
+                    &mut *self.reader.borrow_mut(),
+                    self.header.compression(),
+                    self.header.precision,
+                    region,
+                )?
+                .to_rgba(&handle.kind.kind, self.header.palette()),
+            None => PixelData {
+                width: region.width,
+                height: region.height,
+                pixels: vec![RgbaPixel::new(0, 0, 0, 0); (region.width * region.height) as usize],
+            },
+        };
+        // layers without alpha would be black there
+        let bounds = Rect::new(0, 0, handle.width, handle.height);
+        for (i, pixel) in pixels.pixels.iter_mut().enumerate() {
+            let x = region.x + (i as u32 % region.width) as i32;
+            let y = region.y + (i as u32 / region.width) as i32;
+            if !bounds.contains(x, y) {
+                *pixel = RgbaPixel::new(0, 0, 0, 0);
+            }
+        }
+        Ok(pixels)
+    }
+
+    /// Iterate over the tiles of a layer of this file as they are stored, groups have none.
+    pub fn layer_tiles<'a>(&'a self, handle: &'a LayerHandle) -> TileIter<'a, R> {
+        TileIter {
+            xcf: self,
+            hierarchy: handle.hierarchy.as_ref(),
+            index: 0,
+        }
+    }
+
+    /// Iterate over the tiles of a channel of this file as they are stored.
+    pub fn channel_tiles<'a>(&'a self, handle: &'a ChannelHandle) -> TileIter<'a, R> {
+        TileIter {
+            xcf: self,
+            hierarchy: Some(&handle.hierarchy),
+            index: 0,
+        }
+    }
+
+    /// Decode the pixels of a channel of this file, they are kept for the next calls.
+    pub fn load_channel<'h>(&self, handle: &'h ChannelHandle) -> Result<&'h Channel, Error> {
+        if let Some(channel) = handle.channel.get() {
+            return Ok(channel);
+        }
+        let channel = handle.decode(&mut *self.reader.borrow_mut(), &self.header)?;
+        Ok(handle.channel.get_or_init(|| channel))
+    }
+
+    /// Decode the pixels of the layers and channels which weren't yet.
+    ///
+    /// With the `rayon` feature, the layers and channels are decoded in parallel once their
+    /// tiles are read.
+    pub fn into_xcf(self) -> Result<Xcf, Error> {
+        let mut rdr = self.reader.into_inner();
+        let header = self.header;
+
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for mut handle in self.layers {
+            let layer = handle.layer.take();
+            let tiles = match layer {
+                Some(_) => LayerTiles::default(),
+                None => handle.read_tiles(&mut rdr, &header)?,
+            };
+            layers.push((handle, layer, tiles));
+        }
+        let mut channels = Vec::with_capacity(self.channels.len());
+        for mut handle in self.channels {
+            let channel = handle.channel.take();
+            let tiles = match channel {
+                Some(_) => vec![],
+                None => handle.read_tiles(&mut rdr, &header)?,
+            };
+            channels.push((handle, channel, tiles));
+        }
+
+        #[cfg(feature = "rayon")]
+        let (layers, channels) = (layers.into_par_iter(), channels.into_par_iter());
+        #[cfg(not(feature = "rayon"))]
+        let (layers, channels) = (layers.into_iter(), channels.into_iter());
+        let layers = layers
+            .map(|(handle, layer, tiles)| match layer {
+                Some(layer) => Ok(layer),
+                None => handle.build(tiles, &header),
+            })
+            .collect::<Result<_, _>>()?;
+        let channels = channels
+            .map(|(handle, channel, tiles)| match channel {
+                Some(channel) => Ok(channel),
+                None => handle.build(&tiles, &header),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Xcf {
+            header,
+            layers,
+            channels,
+        })
+    }
+}
+
+impl<R: Read + Seek + std::fmt::Debug> Iterator for TileIter<'_, R> {
+    type Item = Result<Tile, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let hierarchy = self.hierarchy?;
+        if self.index + 1 >= hierarchy.tile_pointers.len() {
+            return None;
+        }
+        let tile = hierarchy.read_tile(
+            &mut *self.xcf.reader.borrow_mut(),
+            self.index,
+            self.xcf.header.compression(),
+        );
+        // stop after an error, the next tiles can't be trusted
+        self.index = match tile {
+            Ok(_) => self.index + 1,
+            Err(_) => hierarchy.tile_pointers.len(),
+        };
+        Some(tile)
+    }
+}
+
+impl<'a> LayerNode<'a> {
+    pub fn is_group(&self) -> bool {
+        self.layer.is_group()
+    }
+
+    /// Find a layer of the group by a path relative to it, e.g. `"Hero/Arm"`.
+    pub fn find(&self, path: &str) -> Option<&LayerNode<'a>> {
+        LayerNode::find_in(&self.children, path)
+    }
+
+    fn find_in<'n>(nodes: &'n [LayerNode<'a>], path: &str) -> Option<&'n LayerNode<'a>> {
+        let (name, rest) = match path.split_once('/') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let node = nodes.iter().find(|n| n.layer.name == name)?;
+        match rest {
+            Some(rest) => LayerNode::find_in(&node.children, rest),
+            None => Some(node),
+        }
+    }
+
+    /// Iterate over the layers of the group and all their descendants, depth first.
+    pub fn descendants(&self) -> impl Iterator<Item = &LayerNode<'a>> {
+        let mut stack: Vec<&LayerNode<'a>> = self.children.iter().rev().collect();
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
 }
 
 impl XcfHeader {
+    /// Get the colormap of an indexed image.
+    pub fn palette(&self) -> Option<&Palette> {
+        self.properties.iter().find_map(|p| match &p.payload {
+            PropertyPayload::ColorMap(palette) => Some(palette),
+            _ => None,
+        })
+    }
+
+    /// Get the compression used for the tiles of the image.
+    ///
+    /// Like GIMP, tiles are considered uncompressed when the image has no compression property.
+    pub fn compression(&self) -> XcfCompression {
+        self.properties
+            .iter()
+            .find_map(|p| match p.payload {
+                PropertyPayload::Compression(compression) => Some(compression),
+                _ => None,
+            })
+            .unwrap_or(XcfCompression::None)
+    }
+
     fn parse<R: Read + std::fmt::Debug>(mut rdr: R) -> Result<XcfHeader, Error> {
         let mut magic = [0u8; 9];
         rdr.read_exact(&mut magic)?;
@@ -369,7 +1485,7 @@ impl XcfHeader {
             Precision::NonLinearU8
         };
 
-        let properties = Property::parse_list(&mut rdr)?;
+        let properties = Property::parse_list(&mut rdr, version)?;
 
         Ok(XcfHeader {
             version,
@@ -1,12 +1,23 @@
+use std::cmp;
 use std::io::{Read, Seek, SeekFrom};
 
 use byteorder::{BigEndian, ReadBytesExt};
+use flate2::read::ZlibDecoder;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
+use crate::LayerColorValue;
 use crate::data::error::Error;
-use crate::data::pixeldata::PixelData;
+use crate::data::hierarchy::Hierarchy;
+use crate::data::palette::Palette;
+use crate::data::pixeldata::{Components, NativePixelData, PixelData};
+use crate::data::precision::Precision;
+use crate::data::rect::Rect;
 use crate::data::rgba::RgbaPixel;
+use crate::data::tiles::Tile;
 use crate::data::version::Version;
-use crate::TileCursor;
+use crate::data::xcf::XcfCompression;
+use crate::rle;
 
 pub trait ParseVersion {
     fn parse<R: Read + std::fmt::Debug>(rdr: R) -> Result<Self, Error>
@@ -31,23 +42,66 @@ impl ParseVersion for Version {
 }
 
 impl PixelData {
-    /// Parses the (silly?) hierarchy structure in the xcf file into a pixel array
-    /// Makes lots of assumptions! Only supports RGBA for now.
+    /// Parses the (silly?) hierarchy structure in the xcf file into a RGBA pixel array.
+    ///
+    /// The color type is guessed from the number of bytes per pixel, with 8 bits per channel,
+    /// prefer [`NativePixelData::parse_hierarchy`] and [`NativePixelData::to_rgba`] when it is
+    /// known.
     pub fn parse_hierarchy<R: Read + Seek + std::fmt::Debug>(
-        mut rdr: R,
+        rdr: R,
         version: Version,
+        compression: XcfCompression,
     ) -> Result<PixelData, Error> {
+        let native =
+            NativePixelData::parse_hierarchy(rdr, version, compression, Precision::NonLinearU8)?;
+        let kind = match native.bpp {
+            1 => LayerColorValue::Grayscale,
+            2 => LayerColorValue::GrayscaleWithAlpha,
+            3 => LayerColorValue::Rgb,
+            _ => LayerColorValue::Rgba,
+        };
+        Ok(native.to_rgba(&kind, None))
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<RgbaPixel> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.pixels[(y * self.width + x) as usize])
+    }
+
+    /// Creates a raw sub buffer from self.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a pixel access is out of bounds.
+    pub fn raw_sub_rgba_buffer(&self, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut sub = Vec::with_capacity((width * height * 4) as usize);
+        for _y in y..(y + height) {
+            for _x in x..(x + width) {
+                if _y > self.height || _x > self.width {
+                    panic!("Pixel access is out of bounds");
+                }
+                sub.extend_from_slice(&self.pixel(_x, _y).unwrap().0);
+            }
+        }
+        sub
+    }
+}
+
+impl Hierarchy {
+    /// Parses the hierarchy structure and the tile pointers of its first level, the tiles
+    /// themselves are left in the file.
+    pub fn parse<R: Read + Seek + std::fmt::Debug>(
+        mut rdr: R,
+        version: Version,
+    ) -> Result<Hierarchy, Error> {
         // read the hierarchy
         let width = rdr.read_u32::<BigEndian>()?;
         let height = rdr.read_u32::<BigEndian>()?;
         let bpp = rdr.read_u32::<BigEndian>()?;
-        /*
-        if bpp != 3 && bpp != 4 {
-            return Err(Error::NotSupported);
-        }
-        */
         let lptr = rdr.read_uint::<BigEndian>(version.bytes_per_offset())?;
-        let _dummpy_ptr_pos = rdr.stream_position()?;
+        // TODO: dummy levels? do we need to consider them?
         rdr.seek(SeekFrom::Start(lptr))?;
         // read the level
         let level_width = rdr.read_u32::<BigEndian>()?;
@@ -56,64 +110,414 @@ impl PixelData {
             return Err(Error::InvalidFormat);
         }
 
-        let mut pixels = vec![RgbaPixel([0, 0, 0, 255]); (width * height) as usize];
-        let mut next_tptr_pos;
+        let (tiles_x, tiles_y) = (width.div_ceil(64), height.div_ceil(64));
+        let tiles = tiles_x.checked_mul(tiles_y).ok_or(Error::InvalidFormat)?;
+        // the terminating null pointer is kept: compressed tiles have no size field, so the
+        // size of a tile is the distance between its pointer and the next one. The number of
+        // tiles comes from the file, nothing is allocated before their pointers are read.
+        let mut tile_pointers = vec![];
+        for _ in 0..=tiles {
+            tile_pointers.push(rdr.read_uint::<BigEndian>(version.bytes_per_offset())?);
+        }
+
+        Ok(Hierarchy {
+            width,
+            height,
+            bpp,
+            tile_pointers,
+        })
+    }
+
+    /// Area covered by the hierarchy.
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Number of tiles in a row.
+    pub fn tiles_x(&self) -> u32 {
+        self.width.div_ceil(64)
+    }
+
+    /// Number of rows of tiles.
+    pub fn tiles_y(&self) -> u32 {
+        self.height.div_ceil(64)
+    }
+
+    /// Area covered by the tile at `index` in the list of tile pointers.
+    pub fn tile_rect(&self, index: usize) -> Rect {
+        let (tx, ty) = (index as u32 % self.tiles_x(), index as u32 / self.tiles_x());
+        let (x, y) = (tx * 64, ty * 64);
+        Rect::new(
+            x as i32,
+            y as i32,
+            cmp::min(64, self.width - x),
+            cmp::min(64, self.height - y),
+        )
+    }
 
-        let tiles_x = (f64::from(width) / 64.0).ceil() as u32;
-        let tiles_y = (f64::from(height) / 64.0).ceil() as u32;
-        for ty in 0..tiles_y {
-            for tx in 0..tiles_x {
-                let tptr = rdr.read_uint::<BigEndian>(version.bytes_per_offset())?;
-                next_tptr_pos = rdr.stream_position()?;
-                rdr.seek(SeekFrom::Start(tptr))?;
+    /// Read the tile at `index` as it is stored in the file.
+    ///
+    /// Compressed tiles have no size field, they span until the next tile.
+    pub fn read_tile<R: Read + Seek>(
+        &self,
+        mut rdr: R,
+        index: usize,
+        compression: XcfCompression,
+    ) -> Result<Tile, Error> {
+        let tptr = self.tile_pointers[index];
+        rdr.seek(SeekFrom::Start(tptr))?;
+        let rect = self.tile_rect(index);
+        let raw_size = u64::from(rect.width * rect.height * self.bpp);
+        let next_tptr = self.tile_pointers[index + 1];
+        let size = match (compression, next_tptr) {
+            (XcfCompression::None, _) => raw_size,
+            // the last tile has no next pointer, like GIMP allow it to be up to 1.5 times the
+            // raw size of a whole tile with zlib, a byte of RLE takes 4 bytes at worst
+            (XcfCompression::Zlib, 0) => u64::from(64 * 64 * self.bpp) * 3 / 2,
+            (_, 0) => raw_size * 4,
+            (_, next_tptr) => next_tptr.saturating_sub(tptr),
+        };
+        let mut data = Vec::new();
+        rdr.take(size).read_to_end(&mut data)?;
+        if compression == XcfCompression::None && data.len() as u64 != raw_size {
+            return Err(Error::InvalidFormat);
+        }
+        let mut tile = Tile {
+            column: index as u32 % self.tiles_x(),
+            row: index as u32 / self.tiles_x(),
+            rect,
+            compression,
+            bpp: self.bpp,
+            data,
+        };
+        if next_tptr == 0 {
+            // drop what comes after the last tile
+            let (_, length) = tile.decode_with_length()?;
+            tile.data.truncate(length);
+        }
+        Ok(tile)
+    }
 
-                let mut cursor = TileCursor::new(width, height, tx, ty, bpp);
-                cursor.feed(&mut rdr, &mut pixels)?;
+    /// Decodes every tile into the channels of each pixel, in the order they are stored.
+    ///
+    /// Channels wider than a byte are stored big-endian, in tiles compressed with RLE each byte
+    /// of a pixel is a plane of its own so they come out in the same order.
+    pub fn decode<R: Read + Seek + std::fmt::Debug>(
+        &self,
+        rdr: R,
+        compression: XcfCompression,
+        precision: Precision,
+    ) -> Result<NativePixelData, Error> {
+        self.decode_region(rdr, compression, precision, self.bounds())
+    }
 
-                rdr.seek(SeekFrom::Start(next_tptr_pos))?;
+    /// Decodes the pixels of `region` like [`Hierarchy::decode`], only the tiles which
+    /// intersect it are read.
+    ///
+    /// The returned pixels have the size of `region`, the parts of it outside of the hierarchy
+    /// are zeroed.
+    pub fn decode_region<R: Read + Seek + std::fmt::Debug>(
+        &self,
+        rdr: R,
+        compression: XcfCompression,
+        precision: Precision,
+        region: Rect,
+    ) -> Result<NativePixelData, Error> {
+        let tiles = self.read_tiles(rdr, compression, region)?;
+        self.assemble(&tiles, precision, region)
+    }
+
+    /// Read the tiles which intersect `region`, row by row.
+    pub(crate) fn read_tiles<R: Read + Seek>(
+        &self,
+        mut rdr: R,
+        compression: XcfCompression,
+        region: Rect,
+    ) -> Result<Vec<Tile>, Error> {
+        let mut tiles = vec![];
+        if let Some(area) = region.intersection(&self.bounds()) {
+            let (first_x, first_y) = (area.x as u32 / 64, area.y as u32 / 64);
+            let last_x = (area.x as u32 + area.width - 1) / 64;
+            let last_y = (area.y as u32 + area.height - 1) / 64;
+            for ty in first_y..=last_y {
+                for tx in first_x..=last_x {
+                    let index = (ty * self.tiles_x() + tx) as usize;
+                    tiles.push(self.read_tile(&mut rdr, index, compression)?);
+                }
             }
         }
+        Ok(tiles)
+    }
 
-        // rdr.seek(SeekFrom::Start(dummpy_ptr_pos))?;
-        // TODO: dummy levels? do we need to consider them?
-        // if we do:
-        /*loop {
-            let dummy_level_ptr = rdr.read_u32::<BigEndian>()?;
-            if dummy_level_ptr == 0 {
-                break;
+    /// Decode the tiles read by [`Hierarchy::read_tiles`] into the pixels of `region`.
+    ///
+    /// With the `rayon` feature, the rows of tiles are decoded in parallel.
+    pub(crate) fn assemble(
+        &self,
+        tiles: &[Tile],
+        precision: Precision,
+        region: Rect,
+    ) -> Result<NativePixelData, Error> {
+        let bpp = self.bpp as usize;
+        let row_length = region.width as usize * bpp;
+        let mut pixels = vec![0; region.height as usize * row_length];
+
+        if let Some(area) = region.intersection(&self.bounds()) {
+            // split the pixels along the rows of tiles, so that each can be filled on its own
+            let mut rows = vec![];
+            let start = (area.y - region.y) as usize * row_length;
+            let mut rest = &mut pixels[start..];
+            let columns = ((area.x as u32 + area.width - 1) / 64 - area.x as u32 / 64) as usize;
+            for tiles in tiles.chunks(columns + 1) {
+                let Some(rows_of_tiles) = tiles[0].rect.intersection(&area) else {
+                    return Err(Error::InvalidFormat);
+                };
+                let length = rows_of_tiles.height as usize * row_length;
+                let (pixels, tail) = std::mem::take(&mut rest).split_at_mut(length);
+                rows.push((rows_of_tiles.y, tiles, pixels));
+                rest = tail;
             }
-        }*/
-        // we are now at the end of the heirarchy structure.
 
-        Ok(PixelData {
-            pixels,
-            width,
-            height,
+            let fill = |(top, tiles, pixels): (i32, &[Tile], &mut [u8])| {
+                for tile in tiles {
+                    let (rect, tile) = (tile.rect, tile.decode()?);
+                    // copy the rows of the tile which are in the region
+                    let Some(copy) = rect.intersection(&area) else {
+                        continue;
+                    };
+                    let length = copy.width as usize * bpp;
+                    for y in copy.y..copy.y + copy.height as i32 {
+                        let from = ((y - rect.y) as usize * rect.width as usize
+                            + (copy.x - rect.x) as usize)
+                            * bpp;
+                        let to =
+                            (y - top) as usize * row_length + (copy.x - region.x) as usize * bpp;
+                        pixels[to..to + length].copy_from_slice(&tile[from..from + length]);
+                    }
+                }
+                Ok::<_, Error>(())
+            };
+            #[cfg(feature = "rayon")]
+            rows.into_par_iter().try_for_each(fill)?;
+            #[cfg(not(feature = "rayon"))]
+            rows.into_iter().try_for_each(fill)?;
+        }
+
+        Ok(NativePixelData {
+            width: region.width,
+            height: region.height,
+            bpp: self.bpp,
+            precision,
+            data: pixels,
         })
     }
+}
 
-    pub fn pixel(&self, x: u32, y: u32) -> Option<RgbaPixel> {
+impl Tile {
+    /// Decode the channels of the pixels of the tile, row by row, like
+    /// [`NativePixelData::data`].
+    pub fn decode(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.decode_with_length()?.0)
+    }
+
+    /// Decode the tile, along with the number of bytes of `data` it took.
+    fn decode_with_length(&self) -> Result<(Vec<u8>, usize), Error> {
+        let (width, height) = (self.rect.width, self.rect.height);
+        let mut pixels = vec![0; (width * height * self.bpp) as usize];
+        let length = match self.compression {
+            XcfCompression::Zlib => {
+                let mut decoder = ZlibDecoder::new(&self.data[..]);
+                let mut tile = Vec::with_capacity(pixels.len());
+                // a byte more than the tile is enough to tell that the data is too long
+                (&mut decoder)
+                    .take(pixels.len() as u64 + 1)
+                    .read_to_end(&mut tile)?;
+                if tile.len() != pixels.len() {
+                    return Err(Error::InvalidFormat);
+                }
+                pixels = tile;
+                decoder.total_in() as usize
+            }
+            XcfCompression::None => {
+                let data = self.data.get(..pixels.len()).ok_or(Error::InvalidFormat)?;
+                pixels.copy_from_slice(data);
+                pixels.len()
+            }
+            XcfCompression::Rle => {
+                // each channel is stored as a plane of its own
+                let bpp = self.bpp as usize;
+                let mut position = 0;
+                for channel in 0..bpp {
+                    let data = self.data.get(position..).ok_or(Error::InvalidFormat)?;
+                    position += rle::rle_decode(data, &mut pixels[channel..], bpp)?;
+                }
+                position
+            }
+            XcfCompression::Fractal => return Err(Error::NotSupported),
+        };
+        Ok((pixels, length))
+    }
+}
+
+impl NativePixelData {
+    /// Parses the hierarchy structure in the xcf file into the channels of each pixel, see
+    /// [`Hierarchy::decode`].
+    pub fn parse_hierarchy<R: Read + Seek + std::fmt::Debug>(
+        mut rdr: R,
+        version: Version,
+        compression: XcfCompression,
+        precision: Precision,
+    ) -> Result<NativePixelData, Error> {
+        Hierarchy::parse(&mut rdr, version)?.decode(rdr, compression, precision)
+    }
+
+    /// Get the channels of a pixel.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<&[u8]> {
         if x >= self.width || y >= self.height {
             return None;
         }
-        Some(self.pixels[(y * self.width + x) as usize])
+        let bpp = self.bpp as usize;
+        let start = (y as usize * self.width as usize + x as usize) * bpp;
+        Some(&self.data[start..start + bpp])
     }
 
-    /// Creates a raw sub buffer from self.
+    /// Decode the channels of every pixel at their own precision.
+    pub fn components(&self) -> Components {
+        let data = &self.data;
+        if self.precision.bytes_per_component() == 1 {
+            return Components::U8(data.clone());
+        }
+        let be16 = || {
+            data.chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        };
+        let be32 = || {
+            data.chunks_exact(4)
+                .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        match (
+            self.precision.bytes_per_component(),
+            self.precision.is_float(),
+        ) {
+            (2, false) => Components::U16(be16().collect()),
+            (2, true) => Components::F16(be16().collect()),
+            (4, false) => Components::U32(be32().collect()),
+            (4, true) => Components::F32(be32().map(f32::from_bits).collect()),
+            _ => Components::F64(
+                data.chunks_exact(8)
+                    .map(|b| f64::from_be_bytes(b.try_into().unwrap()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Scale every channel down to 8 bits.
     ///
-    /// # Panics
+    /// The values are not converted between linear and gamma corrected light, floating point
+    /// values are clamped to `0.0..=1.0`.
+    pub fn to_u8(&self) -> Vec<u8> {
+        let clamp = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        match self.components() {
+            Components::U8(values) => values,
+            Components::U16(values) => values
+                .into_iter()
+                .map(|v| ((u32::from(v) * 255 + 32767) / 65535) as u8)
+                .collect(),
+            Components::U32(values) => values
+                .into_iter()
+                .map(|v| {
+                    ((u64::from(v) * 255 + u64::from(u32::MAX / 2)) / u64::from(u32::MAX)) as u8
+                })
+                .collect(),
+            Components::F16(values) => values
+                .into_iter()
+                .map(|v| clamp(f64::from(f16_to_f32(v))))
+                .collect(),
+            Components::F32(values) => values.into_iter().map(|v| clamp(f64::from(v))).collect(),
+            Components::F64(values) => values.into_iter().map(clamp).collect(),
+        }
+    }
+
+    /// Expand the pixels to RGBA, according to the color type of the layer.
     ///
-    /// Panics if a pixel access is out of bounds.
-    pub fn raw_sub_rgba_buffer(&self, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
-        let mut sub = Vec::with_capacity((width * height * 4) as usize);
-        for _y in y..(y + height) {
-            for _x in x..(x + width) {
-                if _y > self.height || _x > self.width {
-                    panic!("Pixel access is out of bounds");
-                }
-                sub.extend_from_slice(&self.pixel(_x, _y).unwrap().0);
-            }
+    /// Channels are scaled down to 8 bits, see [`NativePixelData::to_u8`]. Indexed pixels are
+    /// looked up in `palette`, indices outside of it are black. Without a palette they are read
+    /// as gray levels.
+    pub fn to_rgba(&self, kind: &LayerColorValue, palette: Option<&Palette>) -> PixelData {
+        let channels = kind.channels() as usize;
+        let indexed = |index: u8, alpha: u8| {
+            let mut pixel = match palette {
+                Some(palette) => palette.color(index).unwrap_or(RgbaPixel::new(0, 0, 0, 255)),
+                None => RgbaPixel::new(index, index, index, 255),
+            };
+            pixel.0[3] = alpha;
+            pixel
+        };
+        let pixels = self
+            .to_u8()
+            .chunks_exact(channels)
+            .map(|c| match kind {
+                LayerColorValue::Rgb => RgbaPixel::new(c[0], c[1], c[2], 255),
+                LayerColorValue::Rgba => RgbaPixel::new(c[0], c[1], c[2], c[3]),
+                LayerColorValue::Grayscale => RgbaPixel::new(c[0], c[0], c[0], 255),
+                LayerColorValue::GrayscaleWithAlpha => RgbaPixel::new(c[0], c[0], c[0], c[1]),
+                LayerColorValue::Indexed => indexed(c[0], 255),
+                LayerColorValue::IndexedWithAlpha => indexed(c[0], c[1]),
+            })
+            .collect();
+        PixelData {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+}
+
+impl Components {
+    pub fn len(&self) -> usize {
+        match self {
+            Components::U8(values) => values.len(),
+            Components::U16(values) | Components::F16(values) => values.len(),
+            Components::U32(values) => values.len(),
+            Components::F32(values) => values.len(),
+            Components::F64(values) => values.len(),
         }
-        sub
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Convert the values to `f32`, integers are scaled to `0.0..=1.0`.
+    pub fn to_f32(&self) -> Vec<f32> {
+        match self {
+            Components::U8(values) => values.iter().map(|&v| f32::from(v) / 255.0).collect(),
+            Components::U16(values) => values.iter().map(|&v| f32::from(v) / 65535.0).collect(),
+            Components::U32(values) => values
+                .iter()
+                .map(|&v| (f64::from(v) / f64::from(u32::MAX)) as f32)
+                .collect(),
+            Components::F16(values) => values.iter().map(|&v| f16_to_f32(v)).collect(),
+            Components::F32(values) => values.clone(),
+            Components::F64(values) => values.iter().map(|&v| v as f32).collect(),
+        }
+    }
+}
+
+/// Convert the bits of an IEEE 754 half precision float.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = u32::from(bits >> 15) << 31;
+    let exponent = u32::from((bits >> 10) & 0x1f);
+    let mantissa = u32::from(bits & 0x3ff);
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            // subnormal, 2^-24 is the value of the lowest bit
+            let value = mantissa as f32 * f32::from_bits(0x3380_0000);
+            return if sign != 0 { -value } else { value };
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
 }
@@ -1,3 +1,5 @@
+use crate::data::error::Error;
+
 pub fn short_run_len_identical(verbatim: &[u8]) -> Vec<u8> {
     if verbatim.is_empty() {
         panic!("Wrong verbatim lengh : {}", verbatim.len());
@@ -177,86 +179,90 @@ pub fn rle_compress(data: &Vec<u8>) -> Vec<u8> {
     compress_data
 }
 
-pub fn rle_decompress(data: &Vec<u8>) -> Vec<u8> {
-    let mut decompress_data = vec![];
-
-    let mut is_short_run_len_identical = false;
-    let mut short_run_len_inc = 0;
-
-    let mut is_long_run_identical = false;
-    let mut wait_long_run_inc = 0;
-    let mut long_run_identical_len = 0;
-
-    let mut diff_buff = vec![];
-    let mut is_short_run_len_diff = false;
-    let mut short_run_len = 0;
-
-    let mut is_long_run_diff = false;
-    let mut long_run_diff_len:u16 = 0;
+/// A run of an RLE stream.
+enum Run<'a> {
+    /// A byte repeated a number of times.
+    Identical(u8, usize),
+    /// Bytes copied as they are.
+    Different(&'a [u8]),
+}
 
-    let mut inc = 0;
-    for byte in data {
-        inc += 1;
-        if is_short_run_len_identical {
-            decompress_data.extend_from_slice(&vec![*byte; short_run_len_inc + 1]);
-            is_short_run_len_identical = false;
-            continue;
+impl Run<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Run::Identical(_, length) => *length,
+            Run::Different(values) => values.len(),
         }
-        if is_long_run_identical {
-            if long_run_identical_len == 0 {
-                long_run_identical_len = *byte as u16 * 256 + data[inc] as u16;
-                wait_long_run_inc = 2;
-                continue;
-            }
-            wait_long_run_inc -= 1;
-            if wait_long_run_inc == 0 {
-                decompress_data.extend_from_slice(&vec![*byte; long_run_identical_len as usize]);
-                long_run_identical_len = 0;
-                wait_long_run_inc = 0;
-                is_long_run_identical = false;
-            }
-            continue;
+    }
+}
+
+/// Read the run at the start of `data`, along with the number of bytes it takes.
+fn next_run(data: &[u8]) -> Result<(Run<'_>, usize), Error> {
+    let byte = |i: usize| data.get(i).copied().ok_or(Error::InvalidFormat);
+    let determinant = byte(0)?;
+    Ok(match determinant {
+        // a short run of identical bytes
+        0..=126 => (Run::Identical(byte(1)?, usize::from(determinant) + 1), 2),
+        // a long run of identical bytes
+        127 => {
+            let length = u16::from_be_bytes([byte(1)?, byte(2)?]);
+            (Run::Identical(byte(3)?, usize::from(length)), 4)
         }
-        diff_buff.push(*byte);
-        if is_short_run_len_diff && short_run_len != 0 {
-            short_run_len -= 1;
-            if short_run_len == 0 {
-                decompress_data.extend_from_slice(&diff_buff);
-                is_short_run_len_diff = false;
-            }
-            continue;
+        // a long run of different bytes
+        128 => {
+            let length = usize::from(u16::from_be_bytes([byte(1)?, byte(2)?]));
+            let values = data.get(3..3 + length).ok_or(Error::InvalidFormat)?;
+            (Run::Different(values), 3 + length)
         }
-        if is_long_run_diff {
-            if long_run_diff_len == 0 {
-                long_run_diff_len = *byte as u16 * 256 + data[inc] as u16 + 1;
-                continue;
-            }
-            long_run_diff_len -= 1;
-            if long_run_diff_len == 0 {
-                decompress_data.extend_from_slice(&diff_buff[2..]);
-                is_long_run_diff = false;
-            }
-            continue;
+        // a short run of different bytes
+        _ => {
+            let length = 256 - usize::from(determinant);
+            let values = data.get(1..1 + length).ok_or(Error::InvalidFormat)?;
+            (Run::Different(values), 1 + length)
         }
+    })
+}
 
-        if *byte <= 126 {
-            is_short_run_len_identical = true;
-            short_run_len_inc = *byte as usize;
-            continue;
+/// Decode the RLE stream at the start of `data` into `plane`, writing a byte every `stride`
+/// bytes: 1 fills the whole plane, the number of bytes per pixel fills a channel of
+/// interleaved pixels.
+///
+/// Returns the number of bytes of `data` the stream took. Truncated streams and runs going past
+/// the end of `plane` are an error.
+///
+/// <https://testing.developer.gimp.org/core/standards/xcf/#rle-compressed-tile-data>
+pub fn rle_decode(data: &[u8], plane: &mut [u8], stride: usize) -> Result<usize, Error> {
+    let length = plane.len().div_ceil(stride);
+    let (mut position, mut i) = (0, 0);
+    while i < length {
+        let (run, size) = next_run(&data[position..])?;
+        if i + run.len() > length {
+            return Err(Error::InvalidFormat);
         }
-        if *byte == 127 {
-            is_long_run_identical = true;
-            continue;
+        let values = plane[i * stride..].iter_mut().step_by(stride);
+        match run {
+            Run::Identical(value, length) => values.take(length).for_each(|v| *v = value),
+            Run::Different(bytes) => values.zip(bytes).for_each(|(v, byte)| *v = *byte),
         }
+        i += run.len();
+        position += size;
+    }
+    Ok(position)
+}
 
-        diff_buff = vec![];
-        if *byte == 128 {
-            is_long_run_diff = true;
-        }
-        if *byte >= 129 {
-            is_short_run_len_diff = true;
-            short_run_len = 255 - *byte + 1;
+/// Decode a whole RLE stream, whatever the number of bytes it holds.
+pub fn rle_decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decompress_data = vec![];
+    let mut position = 0;
+    while position < data.len() {
+        let (run, size) = next_run(&data[position..])?;
+        match run {
+            Run::Identical(value, length) => {
+                decompress_data.resize(decompress_data.len() + length, value)
+            }
+            Run::Different(bytes) => decompress_data.extend_from_slice(bytes),
         }
+        position += size;
     }
-    decompress_data
+    Ok(decompress_data)
 }
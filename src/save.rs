@@ -0,0 +1,442 @@
+//! Write an image back to an XCF file, with everything that was read from it.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use flate2::write::ZlibEncoder;
+
+use crate::LayerColorValue;
+use crate::data::channel::Channel;
+use crate::data::error::Error;
+use crate::data::layer::Layer;
+use crate::data::pixeldata::NativePixelData;
+use crate::data::precision::Precision;
+use crate::data::property::{ParasiteProperty, Property, PropertyPayload};
+use crate::data::version::Version;
+use crate::data::xcf::{Xcf, XcfCompression};
+use crate::rle::rle_compress;
+
+/// The level of the zlib compression of the tiles when none is given, the default of zlib.
+pub(crate) const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+
+/// The file being written, pointers are written as zeros and patched once what they point to
+/// is written.
+struct Writer {
+    data: Vec<u8>,
+    version: Version,
+    compression: XcfCompression,
+    /// The level of the zlib compression, from 0 to 9.
+    level: u32,
+}
+
+impl Writer {
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Write a null pointer, returns where it is to patch it.
+    fn pointer(&mut self) -> usize {
+        let at = self.data.len();
+        self.data.resize(at + self.version.bytes_per_offset(), 0);
+        at
+    }
+
+    /// Make the pointer at `at` point to the end of the file.
+    fn patch(&mut self, at: usize) {
+        let size = self.version.bytes_per_offset();
+        let value = (self.data.len() as u64).to_be_bytes();
+        self.data[at..at + size].copy_from_slice(&value[8 - size..]);
+    }
+
+    fn string(&mut self, value: &str) {
+        string(&mut self.data, value);
+    }
+
+    fn properties(&mut self, properties: &[Property]) -> Result<(), Error> {
+        write_properties(&mut self.data, properties, self.version)?;
+        // PROP_END
+        self.u32(0);
+        self.u32(0);
+        Ok(())
+    }
+
+    fn layer(&mut self, layer: &Layer, xcf: &Xcf) -> Result<(), Error> {
+        self.u32(layer.width);
+        self.u32(layer.height);
+        self.u32(layer.kind.kind.clone() as u32);
+        self.string(&layer.name);
+        self.properties(&layer.properties)?;
+        let hierarchy = self.pointer();
+        let mask = self.pointer();
+        self.patch(hierarchy);
+        let pixels = layer_pixels(layer, xcf)?;
+        self.hierarchy(&pixels)?;
+        if let Some(channel) = &layer.mask {
+            self.patch(mask);
+            self.channel(channel, xcf)?;
+        }
+        Ok(())
+    }
+
+    fn channel(&mut self, channel: &Channel, xcf: &Xcf) -> Result<(), Error> {
+        self.u32(channel.width);
+        self.u32(channel.height);
+        self.string(&channel.name);
+        self.properties(&channel.properties)?;
+        let hierarchy = self.pointer();
+        self.patch(hierarchy);
+        let precision = xcf.header.precision;
+        if channel.pixels.len() != channel.width as usize * channel.height as usize {
+            return Err(Error::InvalidFormat);
+        }
+        let pixels = match &channel.native {
+            Some(native) if (native.width, native.height) != channel.dimensions() => {
+                return Err(Error::InvalidFormat);
+            }
+            // unless the pixels were changed
+            Some(native) if native.to_u8() == channel.pixels => Cow::Borrowed(native),
+            _ => Cow::Owned(NativePixelData {
+                width: channel.width,
+                height: channel.height,
+                bpp: precision.bytes_per_component(),
+                precision,
+                data: from_u8(&channel.pixels, precision),
+            }),
+        };
+        self.hierarchy(&pixels)
+    }
+
+    /// Write the hierarchy of the pixels, with a single level, tiles are compressed like the
+    /// rest of the image.
+    fn hierarchy(&mut self, pixels: &NativePixelData) -> Result<(), Error> {
+        let (width, height, bpp) = (pixels.width, pixels.height, pixels.bpp as usize);
+        if pixels.data.len() != width as usize * height as usize * bpp {
+            return Err(Error::InvalidFormat);
+        }
+        self.u32(width);
+        self.u32(height);
+        self.u32(pixels.bpp);
+        let level = self.pointer();
+        self.pointer();
+
+        self.patch(level);
+        self.u32(width);
+        self.u32(height);
+        let (tiles_x, tiles_y) = (width.div_ceil(64), height.div_ceil(64));
+        let tiles: Vec<usize> = (0..tiles_x * tiles_y).map(|_| self.pointer()).collect();
+        self.pointer();
+        for (i, tile) in tiles.into_iter().enumerate() {
+            let (x, y) = (i as u32 % tiles_x * 64, i as u32 / tiles_x * 64);
+            let tile_width = (width - x).min(64) as usize;
+            let mut data = Vec::with_capacity(tile_width * (height - y).min(64) as usize * bpp);
+            for row in y..(y + 64).min(height) {
+                let start = (row as usize * width as usize + x as usize) * bpp;
+                data.extend_from_slice(&pixels.data[start..start + tile_width * bpp]);
+            }
+            self.patch(tile);
+            self.tile(&data, bpp)?;
+        }
+        Ok(())
+    }
+
+    fn tile(&mut self, data: &[u8], bpp: usize) -> Result<(), Error> {
+        write_tile(&mut self.data, data, bpp, self.compression, self.level)
+    }
+}
+
+/// Write the properties of a file of `version`, but not the end of their list.
+pub(crate) fn write_properties(
+    out: &mut Vec<u8>,
+    properties: &[Property],
+    version: Version,
+) -> Result<(), Error> {
+    for property in properties {
+        let Some(payload) = property.payload.to_bytes(property.length, version)? else {
+            continue;
+        };
+        out.extend_from_slice(&property.kind.to_u32().to_be_bytes());
+        out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        out.extend_from_slice(&payload);
+    }
+    Ok(())
+}
+
+/// Write the interleaved channels of the pixels of a tile, `level` being the level of the zlib
+/// compression.
+pub(crate) fn write_tile(
+    out: &mut Vec<u8>,
+    data: &[u8],
+    bpp: usize,
+    compression: XcfCompression,
+    level: u32,
+) -> Result<(), Error> {
+    match compression {
+        XcfCompression::None => out.extend_from_slice(data),
+        XcfCompression::Rle => {
+            // each byte of the pixels is compressed as a plane of its own
+            for channel in 0..bpp {
+                let plane: Vec<u8> = data.iter().skip(channel).step_by(bpp).copied().collect();
+                out.extend(rle_compress(&plane));
+            }
+        }
+        XcfCompression::Zlib => {
+            let mut encoder = ZlibEncoder::new(out, flate2::Compression::new(level));
+            encoder.write_all(data)?;
+            encoder.finish()?;
+        }
+        XcfCompression::Fractal => return Err(Error::NotSupported),
+    }
+    Ok(())
+}
+
+fn string(data: &mut Vec<u8>, value: &str) {
+    data.extend_from_slice(&(value.len() as u32 + 1).to_be_bytes());
+    data.extend_from_slice(value.as_bytes());
+    data.push(0);
+}
+
+/// The pixels of a layer as they are to be stored, those of the file when there are some and
+/// `pixels` weren't changed since.
+fn layer_pixels<'a>(layer: &'a Layer, xcf: &Xcf) -> Result<Cow<'a, NativePixelData>, Error> {
+    if let Some(native) = &layer.native {
+        if (native.width, native.height) != layer.dimensions() {
+            return Err(Error::InvalidFormat);
+        }
+        if native.to_rgba(&layer.kind.kind, xcf.palette()) == layer.pixels {
+            return Ok(Cow::Borrowed(native));
+        }
+    }
+    let precision = xcf.header.precision;
+    let bpp = layer.kind.kind.channels() * precision.bytes_per_component();
+    let size = layer.width as usize * layer.height as usize;
+    let data = if layer.is_group() {
+        // GIMP renders the children of a group on load, its own pixels don't matter
+        vec![0; size * bpp as usize]
+    } else {
+        if (layer.pixels.width, layer.pixels.height) != layer.dimensions() {
+            return Err(Error::InvalidFormat);
+        }
+        if layer.pixels.pixels.len() != size {
+            return Err(Error::InvalidFormat);
+        }
+        let pixels = layer.pixels.pixels.iter();
+        let values: Vec<u8> = match layer.kind.kind {
+            LayerColorValue::Rgb => pixels.flat_map(|p| [p.r(), p.g(), p.b()]).collect(),
+            LayerColorValue::Rgba => pixels.flat_map(|p| p.0).collect(),
+            LayerColorValue::Grayscale => pixels.map(|p| p.r()).collect(),
+            LayerColorValue::GrayscaleWithAlpha => pixels.flat_map(|p| [p.r(), p.a()]).collect(),
+            // the palette indices of changed pixels are unknown
+            LayerColorValue::Indexed | LayerColorValue::IndexedWithAlpha => {
+                return Err(Error::NotSupported);
+            }
+        };
+        from_u8(&values, precision)
+    };
+    Ok(Cow::Owned(NativePixelData {
+        width: layer.width,
+        height: layer.height,
+        bpp,
+        precision,
+        data,
+    }))
+}
+
+/// Scale 8-bit channels up to `precision`, the reverse of [`NativePixelData::to_u8`].
+fn from_u8(values: &[u8], precision: Precision) -> Vec<u8> {
+    let float = |v: u8| f64::from(v) / 255.0;
+    match (precision.bytes_per_component(), precision.is_float()) {
+        (1, _) => values.to_vec(),
+        (2, false) => values
+            .iter()
+            .flat_map(|&v| (u16::from(v) * 257).to_be_bytes())
+            .collect(),
+        (2, true) => values
+            .iter()
+            .flat_map(|&v| f32_to_f16(float(v) as f32).to_be_bytes())
+            .collect(),
+        (4, false) => values
+            .iter()
+            .flat_map(|&v| (u32::from(v) * 0x0101_0101).to_be_bytes())
+            .collect(),
+        (4, true) => values
+            .iter()
+            .flat_map(|&v| (float(v) as f32).to_be_bytes())
+            .collect(),
+        _ => values
+            .iter()
+            .flat_map(|&v| float(v).to_be_bytes())
+            .collect(),
+    }
+}
+
+/// Get the bits of the half float closest to `value`, values too small to be normal are flushed to zero.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent <= 0 {
+        return sign;
+    }
+    if exponent >= 31 {
+        return sign | 0x7c00;
+    }
+    // rounding up may carry into the exponent, which is what it should do
+    let half = (exponent as u32) << 10 | mantissa >> 13;
+    sign | (half + ((mantissa >> 12) & 1)) as u16
+}
+
+impl PropertyPayload {
+    /// Get the bytes of the payload as stored in a file, `None` for the end of a list which is
+    /// written after its properties.
+    fn to_bytes(&self, length: usize, version: Version) -> Result<Option<Vec<u8>>, Error> {
+        use self::PropertyPayload::*;
+        let u32s = |values: &[u32]| values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        Ok(Some(match self {
+            End => return Ok(None),
+            // points to a layer or channel of the file being read, which moves once written
+            FloatingSelection(_) => return Err(Error::NotSupported),
+            ColorMap(palette) => {
+                let mut data = (palette.len() as u32).to_be_bytes().to_vec();
+                if version.num() == 0 {
+                    // a single byte per color, GIMP reads the palette as shades of gray
+                    data.extend(palette.colors.iter().map(|c| c[0]));
+                } else {
+                    data.extend(palette.colors.iter().flatten());
+                }
+                data
+            }
+            Compression(compression) => vec![compression.to_u8()],
+            ResolutionProperty(resolution) => {
+                u32s(&[resolution.xres.to_bits(), resolution.yres.to_bits()])
+            }
+            Parasites(parasites) => {
+                let mut data = vec![];
+                for parasite in parasites {
+                    parasite.write(&mut data);
+                }
+                data
+            }
+            Guides(guides) => guides
+                .iter()
+                .flat_map(|g| {
+                    let mut guide = g.position.to_be_bytes().to_vec();
+                    guide.push(g.orientation);
+                    guide
+                })
+                .collect(),
+            ActiveLayer() | GroupItemLayer() | ActiveChannel() | SelectionChannel() => vec![],
+            OpacityLayer(opacity) => opacity.0.to_vec(),
+            FloatOpacityLayer(opacity) => u32s(&[opacity.to_bits()]),
+            OffsetsLayer(x, y) => u32s(&[*x as u32, *y as u32]),
+            ItemPathLayer(path) => u32s(path),
+            ColorChannel(color) => color.to_vec(),
+            FloatColorChannel(color) => u32s(&color.map(f32::to_bits)),
+            Tatoo(value)
+            | Unit(value)
+            | VisibleLayer(value)
+            | LinkedLayer(value)
+            | ColorTagLayer(value)
+            | LockContentLayer(value)
+            | LockAlphaLayer(value)
+            | LockPositionLayer(value)
+            | LockVisibilityLayer(value)
+            | TextLayerFlagsLayer(value)
+            | ApplyMaskLayer(value)
+            | EditMaskLayer(value)
+            | ShowMaskLayer(value)
+            | ModeLayer(value)
+            | BlendSpaceLayer(value)
+            | CompositeSpaceLayer(value)
+            | CompositeModeLayer(value)
+            | GroupItemFlagsLayer(value)
+            | ShowMaskedChannel(value) => {
+                let mut data = value.to_be_bytes().to_vec();
+                // keep the padding of properties written longer than they are
+                data.resize(length.max(4), 0);
+                data
+            }
+            Unknown(data) => data.clone(),
+        }))
+    }
+}
+
+impl ParasiteProperty {
+    fn write(&self, data: &mut Vec<u8>) {
+        string(data, &self.name);
+        data.extend_from_slice(&self.flags.to_be_bytes());
+        string(data, &self.data);
+    }
+}
+
+impl Xcf {
+    /// Write the image to an XCF file at the path specified, see [`Xcf::write_to`].
+    pub fn save<P: AsRef<Path>>(&self, p: P) -> Result<(), Error> {
+        let mut wtr = BufWriter::new(File::create(p)?);
+        self.write_to(&mut wtr)?;
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Write the image in the XCF format, with the version, precision and compression of its
+    /// header.
+    ///
+    /// The properties of the image, its layers, masks and channels are written as they are,
+    /// including parasites and the properties this crate doesn't know about, so that a loaded
+    /// file is saved without losing anything. The pixels stored in the file (`native`) are
+    /// written back as they are, unless `pixels` were changed: they are then written instead,
+    /// scaled up to the precision of the image. Pixels that don't have the size of their layer
+    /// or channel are an error, and so are floating selections which aren't supported.
+    pub fn write_to<W: Write>(&self, wtr: W) -> Result<(), Error> {
+        self.write_to_with_level(wtr, DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    /// Write the image like [`Xcf::write_to`], zlib compressed tiles with the `level` given, from
+    /// 0 (fastest) to 9 (smallest). Other levels aren't supported.
+    pub fn write_to_with_level<W: Write>(&self, mut wtr: W, level: u32) -> Result<(), Error> {
+        if level > 9 {
+            return Err(Error::NotSupported);
+        }
+        let header = &self.header;
+        let version = header.version;
+        let mut writer = Writer {
+            data: vec![],
+            version,
+            compression: header.compression(),
+            level,
+        };
+        if version.num() == 0 {
+            writer.data.extend_from_slice(b"gimp xcf file\0");
+        } else {
+            writer
+                .data
+                .extend_from_slice(format!("gimp xcf v{:03}\0", version.num()).as_bytes());
+        }
+        writer.u32(header.width);
+        writer.u32(header.height);
+        writer.u32(header.color_type.clone() as u32);
+        if version.num() >= 4 {
+            writer.u32(header.precision.to_u32(version)?);
+        } else if header.precision != Precision::NonLinearU8 {
+            return Err(Error::InvalidPrecision);
+        }
+        writer.properties(&header.properties)?;
+
+        let layers: Vec<usize> = self.layers.iter().map(|_| writer.pointer()).collect();
+        writer.pointer();
+        let channels: Vec<usize> = self.channels.iter().map(|_| writer.pointer()).collect();
+        writer.pointer();
+        for (layer, pointer) in self.layers.iter().zip(layers) {
+            writer.patch(pointer);
+            writer.layer(layer, self)?;
+        }
+        for (channel, pointer) in self.channels.iter().zip(channels) {
+            writer.patch(pointer);
+            writer.channel(channel, self)?;
+        }
+        wtr.write_all(&writer.data)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use xcf_rs::blend::{blend, composite, linear_to_srgb, srgb_to_linear};
+use xcf_rs::data::mode::{LayerCompositeMode, LayerMode};
+
+fn assert_close<const N: usize>(actual: [f32; N], expected: [f32; N]) {
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        assert!((a - e).abs() < 1e-3, "{actual:?} != {expected:?}");
+    }
+}
+
+#[test]
+fn blend_per_channel_modes() {
+    let backdrop = [0.25, 0.5, 1.0];
+    let layer = [0.5, 0.5, 0.0];
+
+    assert_close(
+        blend(LayerMode::Multiply, backdrop, layer),
+        [0.125, 0.25, 0.0],
+    );
+    assert_close(
+        blend(LayerMode::Screen, backdrop, layer),
+        [0.625, 0.75, 1.0],
+    );
+    assert_close(
+        blend(LayerMode::Difference, backdrop, layer),
+        [0.25, 0.0, 1.0],
+    );
+    assert_close(
+        blend(LayerMode::DarkenOnly, backdrop, layer),
+        [0.25, 0.5, 0.0],
+    );
+    assert_close(
+        blend(LayerMode::LightenOnly, backdrop, layer),
+        [0.5, 0.5, 1.0],
+    );
+    assert_close(blend(LayerMode::Overlay, backdrop, layer), [0.25, 0.5, 1.0]);
+    assert_close(blend(LayerMode::Dodge, backdrop, layer), [0.5, 1.0, 1.0]);
+    assert_close(blend(LayerMode::Burn, backdrop, layer), [0.0, 0.0, 1.0]);
+    assert_close(
+        blend(LayerMode::GrainMerge, backdrop, layer),
+        [0.25, 0.5, 0.5],
+    );
+
+    // modern modes aren't clamped, legacy ones are
+    assert_close(
+        blend(LayerMode::Addition, backdrop, layer),
+        [0.75, 1.0, 1.0],
+    );
+    assert_close(
+        blend(LayerMode::Subtract, layer, backdrop),
+        [0.25, 0.0, -1.0],
+    );
+    assert_close(
+        blend(LayerMode::SubtractLegacy, layer, backdrop),
+        [0.25, 0.0, 0.0],
+    );
+}
+
+#[test]
+fn blend_color_modes() {
+    let red = [1.0, 0.0, 0.0];
+    let dark_blue = [0.0, 0.0, 0.5];
+
+    assert_close(blend(LayerMode::HsvHue, dark_blue, red), [0.5, 0.0, 0.0]);
+    assert_close(blend(LayerMode::HsvValue, dark_blue, red), [0.0, 0.0, 1.0]);
+    assert_close(blend(LayerMode::HslColor, [0.5; 3], red), red);
+    assert_close(blend(LayerMode::HslColor, dark_blue, red), [0.5, 0.0, 0.0]);
+
+    // the lightness of a gray is kept by a colorless hue
+    let gray = [0.5; 3];
+    assert_close(blend(LayerMode::LchHue, gray, red), gray);
+    assert_close(blend(LayerMode::LchLightness, red, red), red);
+}
+
+#[test]
+fn composite_modes() {
+    let backdrop = [0.5, 0.5, 0.5, 1.0];
+    let red = [1.0, 0.0, 0.0, 1.0];
+
+    assert_close(
+        composite(
+            LayerMode::Normal,
+            LayerCompositeMode::Union,
+            backdrop,
+            red,
+            0.5,
+        ),
+        [0.75, 0.25, 0.25, 1.0],
+    );
+    assert_close(
+        composite(
+            LayerMode::Multiply,
+            LayerCompositeMode::Union,
+            backdrop,
+            red,
+            1.0,
+        ),
+        [0.5, 0.0, 0.0, 1.0],
+    );
+    // over a transparent backdrop, the layer is left as is
+    assert_close(
+        composite(
+            LayerMode::Multiply,
+            LayerCompositeMode::Union,
+            [0.0; 4],
+            red,
+            1.0,
+        ),
+        red,
+    );
+
+    assert_close(
+        composite(
+            LayerMode::Behind,
+            LayerCompositeMode::Union,
+            backdrop,
+            red,
+            1.0,
+        ),
+        backdrop,
+    );
+    assert_close(
+        composite(
+            LayerMode::Behind,
+            LayerCompositeMode::Union,
+            [0.0; 4],
+            red,
+            0.5,
+        ),
+        [1.0, 0.0, 0.0, 0.5],
+    );
+
+    assert_close(
+        composite(
+            LayerMode::Erase,
+            LayerCompositeMode::Union,
+            backdrop,
+            red,
+            0.25,
+        ),
+        [0.5, 0.5, 0.5, 0.75],
+    );
+    assert_close(
+        composite(
+            LayerMode::Split,
+            LayerCompositeMode::Union,
+            [0.5, 0.5, 0.5, 0.5],
+            red,
+            0.25,
+        ),
+        [0.5, 0.5, 0.5, 0.25],
+    );
+    assert_close(
+        composite(
+            LayerMode::Merge,
+            LayerCompositeMode::Union,
+            [0.0, 0.0, 1.0, 0.5],
+            red,
+            0.5,
+        ),
+        [0.5, 0.0, 0.5, 1.0],
+    );
+
+    // white erased from white is transparent
+    let white = [1.0; 4];
+    assert_eq!(
+        composite(
+            LayerMode::ColorErase,
+            LayerCompositeMode::Union,
+            white,
+            white,
+            1.0
+        )[3],
+        0.0
+    );
+}
+
+#[test]
+fn srgb_conversions() {
+    assert_close([srgb_to_linear(0.5)], [0.214]);
+    assert_close([linear_to_srgb(srgb_to_linear(0.3))], [0.3]);
+}
@@ -0,0 +1,279 @@
+//! Builds small XCF files byte by byte, to test the reader on features that the sample files
+//! do not cover.
+#![allow(dead_code)]
+
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use xcf_rs::rle::rle_compress;
+
+pub struct TestChannel {
+    pub width: u32,
+    pub height: u32,
+    pub name: &'static str,
+    pub properties: Vec<(u32, Vec<u8>)>,
+    pub bpp: u32,
+    /// `width * height * bpp` interleaved bytes.
+    pub data: Vec<u8>,
+}
+
+pub struct TestLayer {
+    pub width: u32,
+    pub height: u32,
+    pub kind: u32,
+    pub name: &'static str,
+    pub properties: Vec<(u32, Vec<u8>)>,
+    pub bpp: u32,
+    /// `width * height * bpp` interleaved bytes.
+    pub data: Vec<u8>,
+    pub mask: Option<TestChannel>,
+}
+
+impl TestLayer {
+    /// A layer of 8-bit RGBA pixels, given row by row.
+    pub fn rgba(name: &'static str, width: u32, height: u32, pixels: &[[u8; 4]]) -> Self {
+        TestLayer {
+            width,
+            height,
+            kind: 1,
+            name,
+            properties: vec![],
+            bpp: 4,
+            data: pixels.iter().flatten().copied().collect(),
+            mask: None,
+        }
+    }
+
+    pub fn with(mut self, property: (u32, Vec<u8>)) -> Self {
+        self.properties.push(property);
+        self
+    }
+}
+
+pub fn float_opacity(opacity: f32) -> (u32, Vec<u8>) {
+    (33, opacity.to_be_bytes().to_vec())
+}
+
+pub fn visible(visible: bool) -> (u32, Vec<u8>) {
+    (8, u32::from(visible).to_be_bytes().to_vec())
+}
+
+pub fn offsets(x: i32, y: i32) -> (u32, Vec<u8>) {
+    let mut payload = x.to_be_bytes().to_vec();
+    payload.extend_from_slice(&y.to_be_bytes());
+    (15, payload)
+}
+
+pub fn apply_mask() -> (u32, Vec<u8>) {
+    (11, vec![0, 0, 0, 1])
+}
+
+pub fn mode(mode: u32) -> (u32, Vec<u8>) {
+    (7, mode.to_be_bytes().to_vec())
+}
+
+pub fn composite_mode(mode: u32) -> (u32, Vec<u8>) {
+    (35, mode.to_be_bytes().to_vec())
+}
+
+pub fn composite_space(space: u32) -> (u32, Vec<u8>) {
+    (36, space.to_be_bytes().to_vec())
+}
+
+pub fn group() -> (u32, Vec<u8>) {
+    (29, vec![])
+}
+
+pub fn item_path(path: &[u32]) -> (u32, Vec<u8>) {
+    (30, path.iter().flat_map(|p| p.to_be_bytes()).collect())
+}
+
+pub struct TestImage {
+    pub version: u16,
+    pub width: u32,
+    pub height: u32,
+    pub color_type: u32,
+    pub precision: u32,
+    /// 0 for uncompressed tiles, 1 for RLE and 2 for zlib.
+    pub compression: u8,
+    pub properties: Vec<(u32, Vec<u8>)>,
+    pub layers: Vec<TestLayer>,
+    pub channels: Vec<TestChannel>,
+}
+
+impl TestImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        TestImage {
+            version: 11,
+            width,
+            height,
+            color_type: 0,
+            precision: 150,
+            compression: 0,
+            properties: vec![],
+            layers: vec![],
+            channels: vec![],
+        }
+    }
+
+    fn pointer(&self, out: &mut Vec<u8>, value: u64) {
+        if self.version >= 11 {
+            out.extend_from_slice(&value.to_be_bytes());
+        } else {
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+    }
+
+    fn patch_pointer(&self, out: &mut [u8], at: usize, value: u64) {
+        if self.version >= 11 {
+            out[at..at + 8].copy_from_slice(&value.to_be_bytes());
+        } else {
+            out[at..at + 4].copy_from_slice(&(value as u32).to_be_bytes());
+        }
+    }
+
+    fn properties(out: &mut Vec<u8>, properties: &[(u32, Vec<u8>)]) {
+        for (id, payload) in properties {
+            out.extend_from_slice(&id.to_be_bytes());
+            out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            out.extend_from_slice(payload);
+        }
+        out.extend_from_slice(&[0; 8]);
+    }
+
+    fn string(out: &mut Vec<u8>, value: &str) {
+        out.extend_from_slice(&(value.len() as u32 + 1).to_be_bytes());
+        out.extend_from_slice(value.as_bytes());
+        out.push(0);
+    }
+
+    fn hierarchy(&self, out: &mut Vec<u8>, width: u32, height: u32, bpp: u32, data: &[u8]) {
+        assert_eq!(data.len(), (width * height * bpp) as usize);
+        out.extend_from_slice(&width.to_be_bytes());
+        out.extend_from_slice(&height.to_be_bytes());
+        out.extend_from_slice(&bpp.to_be_bytes());
+        let level_ptr = out.len() + 2 * self.version_offset();
+        self.pointer(out, level_ptr as u64);
+        self.pointer(out, 0);
+
+        out.extend_from_slice(&width.to_be_bytes());
+        out.extend_from_slice(&height.to_be_bytes());
+        let tiles_x = width.div_ceil(64);
+        let tiles_y = height.div_ceil(64);
+        let table = out.len();
+        for _ in 0..=tiles_x * tiles_y {
+            self.pointer(out, 0);
+        }
+        let mut i = 0;
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                let at = table + i * self.version_offset();
+                let tile_ptr = out.len() as u64;
+                self.patch_pointer(out, at, tile_ptr);
+                let mut tile = vec![];
+                for y in ty * 64..height.min(ty * 64 + 64) {
+                    for x in tx * 64..width.min(tx * 64 + 64) {
+                        let start = ((y * width + x) * bpp) as usize;
+                        tile.extend_from_slice(&data[start..start + bpp as usize]);
+                    }
+                }
+                self.tile(out, &tile, bpp as usize);
+                i += 1;
+            }
+        }
+    }
+
+    fn tile(&self, out: &mut Vec<u8>, tile: &[u8], bpp: usize) {
+        match self.compression {
+            0 => out.extend_from_slice(tile),
+            // every byte of the pixels is a plane of its own
+            1 => {
+                for byte in 0..bpp {
+                    let plane = tile.iter().skip(byte).step_by(bpp).copied().collect();
+                    out.extend(rle_compress(&plane));
+                }
+            }
+            2 => {
+                let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+                encoder.write_all(tile).unwrap();
+                out.extend(encoder.finish().unwrap());
+            }
+            _ => unimplemented!("compression {}", self.compression),
+        }
+    }
+
+    fn version_offset(&self) -> usize {
+        if self.version >= 11 { 8 } else { 4 }
+    }
+
+    fn channel(&self, out: &mut Vec<u8>, channel: &TestChannel) {
+        out.extend_from_slice(&channel.width.to_be_bytes());
+        out.extend_from_slice(&channel.height.to_be_bytes());
+        Self::string(out, channel.name);
+        Self::properties(out, &channel.properties);
+        let hierarchy_ptr = out.len() + self.version_offset();
+        self.pointer(out, hierarchy_ptr as u64);
+        self.hierarchy(
+            out,
+            channel.width,
+            channel.height,
+            channel.bpp,
+            &channel.data,
+        );
+    }
+
+    fn layer(&self, out: &mut Vec<u8>, layer: &TestLayer) {
+        out.extend_from_slice(&layer.width.to_be_bytes());
+        out.extend_from_slice(&layer.height.to_be_bytes());
+        out.extend_from_slice(&layer.kind.to_be_bytes());
+        Self::string(out, layer.name);
+        Self::properties(out, &layer.properties);
+        let pointers = out.len();
+        self.pointer(out, 0);
+        self.pointer(out, 0);
+        let hierarchy_ptr = out.len() as u64;
+        self.patch_pointer(out, pointers, hierarchy_ptr);
+        self.hierarchy(out, layer.width, layer.height, layer.bpp, &layer.data);
+        if let Some(mask) = &layer.mask {
+            let mask_ptr = out.len() as u64;
+            self.patch_pointer(out, pointers + self.version_offset(), mask_ptr);
+            self.channel(out, mask);
+        }
+    }
+
+    /// Serialize the image, every tile being stored with the compression of the image.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = if self.version == 0 {
+            b"gimp xcf file\0".to_vec()
+        } else {
+            format!("gimp xcf v{:03}\0", self.version).into_bytes()
+        };
+        out.extend_from_slice(&self.width.to_be_bytes());
+        out.extend_from_slice(&self.height.to_be_bytes());
+        out.extend_from_slice(&self.color_type.to_be_bytes());
+        if self.version >= 4 {
+            out.extend_from_slice(&self.precision.to_be_bytes());
+        }
+        let mut properties = vec![(17, vec![self.compression])];
+        properties.extend(self.properties.iter().cloned());
+        Self::properties(&mut out, &properties);
+
+        let table = out.len();
+        for _ in 0..self.layers.len() + self.channels.len() + 2 {
+            self.pointer(&mut out, 0);
+        }
+        for (i, layer) in self.layers.iter().enumerate() {
+            let ptr = out.len() as u64;
+            self.patch_pointer(&mut out, table + i * self.version_offset(), ptr);
+            self.layer(&mut out, layer);
+        }
+        for (i, channel) in self.channels.iter().enumerate() {
+            let ptr = out.len() as u64;
+            let at = table + (self.layers.len() + 1 + i) * self.version_offset();
+            self.patch_pointer(&mut out, at, ptr);
+            self.channel(&mut out, channel);
+        }
+        out
+    }
+}
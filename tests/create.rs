@@ -1,12 +1,14 @@
 use sha1::{Digest, Sha1};
 use std::fs::{create_dir, File};
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
 
-use xcf_rs::create::XcfCreator;
+use xcf_rs::create::{XcfBuilder, XcfCreator};
 use xcf_rs::data::layer::Layer;
 use xcf_rs::data::pixeldata::PixelData;
+use xcf_rs::data::precision::Precision;
 use xcf_rs::data::property::{ParasiteProperty, ResolutionProperty};
-use xcf_rs::data::xcf::XcfCompression;
+use xcf_rs::data::xcf::{Xcf, XcfCompression};
 use xcf_rs::{
     data::{
         color::ColorType,
@@ -38,83 +40,9 @@ fn create_file(file_name: &'static str, xcf: &mut XcfCreator)-> Result<(File, Pa
     Ok((new_file, path, compare_path))
 }
 
-#[test]
-fn write_minimal_xcf1() -> Result<(), Error> {
-    let mut xcf = XcfCreator::new(1, 1, 1, ColorType::Rgb);
-    let properties = vec![];
-    xcf.add_properties(&properties);
-    xcf.add_layers(&vec![]);
-    let xcf_file = create_file("minimal_xcf1.xcf", &mut xcf)?;
-    let file_hash = "9e54fb4fc2658de528398a66cc684ada35866807";
-    assert_hash(xcf_file.1.to_str().expect(""), file_hash);
-    assert_hash(xcf_file.2.to_str().expect(""), file_hash);
-    Ok(())
-}
-
-#[test]
-fn write_minimal_xcf3() -> Result<(), Error> {
-    let mut xcf = XcfCreator::new(3, 1, 1, ColorType::Rgb);
-    let properties = vec![];
-    xcf.add_properties(&properties);
-    xcf.add_layers(&vec![]);
-    let xcf_file = create_file("minimal_xcf3.xcf", &mut xcf)?;
-    let file_hash = "1b9d7187a9b783cd3ce16790ab1ebe7a05eac119";
-    assert_hash(xcf_file.1.to_str().expect(""), file_hash);
-    assert_hash(xcf_file.2.to_str().expect(""), file_hash);
-    Ok(())
-}
-
-#[test]
-fn write_minimal_xcf10() -> Result<(), Error> {
-    let mut xcf = XcfCreator::new(10, 1, 1, ColorType::Rgb);
-    let properties = vec![];
-    xcf.add_properties(&properties);
-    xcf.add_layers(&vec![]);
-    let xcf_file = create_file("minimal_xcf10.xcf", &mut xcf)?;
-    let file_hash = "72dbe0106f48fb25d0fd047acf519f13a3dff086";
-    assert_hash(xcf_file.1.to_str().expect(""), file_hash);
-    assert_hash(xcf_file.2.to_str().expect(""), file_hash);
-    Ok(())
-}
-
-#[test]
-fn write_minimal_xcf11_without_properties() -> Result<(), Error> {
-    let mut xcf = XcfCreator::new(11, 1, 1, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
-
-    let mut layers = vec![];
-    let pixels = vec![RgbaPixel::new(158, 36, 222, 0)];
-    let pixels_layer_one: PixelData = PixelData {
-        width: 1,
-        height: 1,
-        pixels: pixels,
-    };
-    let properties_layer_one = vec![];
-    let layer_one = Layer {
-        width: 1,
-        height: 1,
-        kind: LayerColorType {
-            kind: LayerColorValue::Rgb,
-            alpha: false, // TODO : delete ? LayerColorValue can determine alpha value
-        },
-        name: "Background".to_string(),
-        pixels: pixels_layer_one,
-        properties: properties_layer_one,
-    };
-    layers.push(layer_one);
-    xcf.add_layers(&layers);
-    let xcf_file = create_file("minimal_xcf11.xcf", &mut xcf)?;
-    let file_hash = "6d6e2decc5c6393e83c6ac255e99fdf6617c4a95";
-    assert_hash(xcf_file.1.to_str().expect(""), file_hash);
-    assert_hash(xcf_file.2.to_str().expect(""), file_hash);
-    Ok(())
-}
-
-#[test]
-fn write_minimal_xcf11_properties() -> Result<(), Error> {
-    let mut xcf = XcfCreator::new(11, 1, 1, ColorType::Rgb);
-
-    let properties = vec![
+/// The properties of the images GIMP 2.10 writes, those of the reference files.
+fn gimp_properties() -> Vec<Property> {
+    vec![
         Property {
             kind: PropertyIdentifier::PropCompression,
             length: 1,
@@ -154,17 +82,12 @@ fn write_minimal_xcf11_properties() -> Result<(), Error> {
                 }
             ])
         }
-    ];
-    xcf.add_properties(&properties);
+    ]
+}
 
-    let mut layers = vec![];
-    let pixels = vec![RgbaPixel::new(158, 36, 222, 0)];
-    let pixels_layer_one: PixelData = PixelData {
-        width: 1,
-        height: 1,
-        pixels: pixels,
-    };
-    let properties_layer_one = vec![
+/// The properties of the layers GIMP 2.10 writes, those of the reference files.
+fn gimp_layer_properties() -> Vec<Property> {
+    vec![
         Property {
             kind: PropertyIdentifier::PropActiveLayer,
             length: 0,
@@ -178,12 +101,12 @@ fn write_minimal_xcf11_properties() -> Result<(), Error> {
         Property {
             kind: PropertyIdentifier::PropFloatOpacity,
             length: 4,
-            payload: PropertyPayload::FloatOpacityLayer(),
+            payload: PropertyPayload::FloatOpacityLayer(1.0),
         },
         Property {
             kind: PropertyIdentifier::PropVisible,
             length: 4,
-            payload: PropertyPayload::VisibleLayer(),
+            payload: PropertyPayload::VisibleLayer(1),
         },
         Property {
             kind: PropertyIdentifier::PropLinked,
@@ -255,7 +178,140 @@ fn write_minimal_xcf11_properties() -> Result<(), Error> {
             length: 4,
             payload: PropertyPayload::Tatoo(2),
         },
+    ]
+}
+
+/// A 1x1 layer with the properties GIMP writes in old versions.
+fn violet_background() -> Layer {
+    let mut layer = create_layer(1, 1, vec![RgbaPixel::new(158, 36, 222, 255)]);
+    layer.properties = vec![
+        Property {
+            kind: PropertyIdentifier::PropOpacity,
+            length: 4,
+            payload: PropertyPayload::OpacityLayer(RgbaPixel::new(0, 0, 0, 255)),
+        },
+        Property {
+            kind: PropertyIdentifier::PropVisible,
+            length: 4,
+            payload: PropertyPayload::VisibleLayer(1),
+        },
+        Property {
+            kind: PropertyIdentifier::PropMode,
+            length: 4,
+            payload: PropertyPayload::ModeLayer(0),
+        },
     ];
+    layer
+}
+
+fn assert_violet_background(path: &Path) -> Result<(), Error> {
+    let image = Xcf::open(path)?;
+    assert_eq!(image.layers.len(), 1);
+    let layer = &image.layers[0];
+    assert_eq!(layer.name, "Background");
+    assert_eq!(layer.properties, violet_background().properties);
+    assert_eq!(layer.pixel(0, 0), Some(RgbaPixel::new(158, 36, 222, 255)));
+    Ok(())
+}
+
+#[test]
+fn write_minimal_xcf1() -> Result<(), Error> {
+    let mut xcf = XcfCreator::new(1, 1, 1, ColorType::Rgb);
+    let properties = vec![];
+    xcf.add_properties(&properties);
+    xcf.add_layers(&vec![violet_background()]);
+    let xcf_file = create_file("minimal_xcf1.xcf", &mut xcf)?;
+    assert_violet_background(&xcf_file.1)?;
+    let file_hash = "490123a931b9b54aeeaaddd23699e6a4cae32431";
+    assert_hash(xcf_file.1.to_str().expect(""), file_hash);
+    assert_hash(xcf_file.2.to_str().expect(""), file_hash);
+    Ok(())
+}
+
+#[test]
+fn write_minimal_xcf3() -> Result<(), Error> {
+    let mut xcf = XcfCreator::new(3, 1, 1, ColorType::Rgb);
+    let properties = vec![];
+    xcf.add_properties(&properties);
+    xcf.add_layers(&vec![violet_background()]);
+    let xcf_file = create_file("minimal_xcf3.xcf", &mut xcf)?;
+    assert_violet_background(&xcf_file.1)?;
+    let file_hash = "9b8625fec9990deae88d871f1dcc63577a47e304";
+    assert_hash(xcf_file.1.to_str().expect(""), file_hash);
+    assert_hash(xcf_file.2.to_str().expect(""), file_hash);
+    Ok(())
+}
+
+#[test]
+fn write_minimal_xcf10() -> Result<(), Error> {
+    let mut xcf = XcfCreator::new(10, 1, 1, ColorType::Rgb);
+    let properties = vec![];
+    xcf.add_properties(&properties);
+    xcf.add_layers(&vec![violet_background()]);
+    let xcf_file = create_file("minimal_xcf10.xcf", &mut xcf)?;
+    assert_violet_background(&xcf_file.1)?;
+    let file_hash = "dc6503149d96ae9852605849afcfccd85999af0c";
+    assert_hash(xcf_file.1.to_str().expect(""), file_hash);
+    assert_hash(xcf_file.2.to_str().expect(""), file_hash);
+    Ok(())
+}
+
+#[test]
+fn write_minimal_xcf11_without_properties() -> Result<(), Error> {
+    let mut xcf = XcfCreator::new(11, 1, 1, ColorType::Rgb);
+    xcf.add_properties(&vec![]);
+
+    let mut layers = vec![];
+    let pixels = vec![RgbaPixel::new(158, 36, 222, 0)];
+    let pixels_layer_one: PixelData = PixelData {
+        width: 1,
+        height: 1,
+        pixels: pixels,
+    };
+    let properties_layer_one = vec![];
+    let layer_one = Layer {
+        width: 1,
+        height: 1,
+        kind: LayerColorType {
+            kind: LayerColorValue::Rgb,
+            alpha: false, // TODO : delete ? LayerColorValue can determine alpha value
+        },
+        name: "Background".to_string(),
+        pixels: pixels_layer_one,
+        properties: properties_layer_one,
+        native: None,
+        mask: None,
+    };
+    layers.push(layer_one);
+    xcf.add_layers(&layers);
+    let xcf_file = create_file("minimal_xcf11_without_properties.xcf", &mut xcf)?;
+
+    // nothing is added, the tiles aren't compressed
+    let image = Xcf::open(xcf_file.1)?;
+    assert!(image.header.properties.is_empty());
+    assert_eq!(image.header.compression(), XcfCompression::None);
+    assert!(image.layers[0].properties.is_empty());
+    assert_eq!(
+        image.layers[0].pixel(0, 0),
+        Some(RgbaPixel::new(158, 36, 222, 255))
+    );
+    Ok(())
+}
+
+#[test]
+fn write_minimal_xcf11_properties() -> Result<(), Error> {
+    let mut xcf = XcfCreator::new(11, 1, 1, ColorType::Rgb);
+
+    xcf.add_properties(&gimp_properties());
+
+    let mut layers = vec![];
+    let pixels = vec![RgbaPixel::new(158, 36, 222, 0)];
+    let pixels_layer_one: PixelData = PixelData {
+        width: 1,
+        height: 1,
+        pixels: pixels,
+    };
+    let properties_layer_one = gimp_layer_properties();
     let layer_one = Layer {
         width: 1,
         height: 1,
@@ -266,6 +322,8 @@ fn write_minimal_xcf11_properties() -> Result<(), Error> {
         name: "Background".to_string(),
         pixels: pixels_layer_one,
         properties: properties_layer_one,
+        native: None,
+        mask: None,
     };
     layers.push(layer_one);
     xcf.add_layers(&layers);
@@ -279,7 +337,7 @@ fn write_minimal_xcf11_properties() -> Result<(), Error> {
 #[test]
 fn write_minimal_four_pixels() -> Result<(), Error> {
     let mut xcf = XcfCreator::new(11, 2, 2, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
     let pixels = vec![
         RgbaPixel::new(158, 0, 0, 0),   //  #ff0000
@@ -301,7 +359,9 @@ fn write_minimal_four_pixels() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_one,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_one);
     xcf.add_layers(&layers);
@@ -314,7 +374,7 @@ fn write_minimal_four_pixels() -> Result<(), Error> {
 #[test]
 fn write_minimal_nine_pixels() -> Result<(), Error> {
     let mut xcf = XcfCreator::new(11, 3, 3, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
     let pixels = vec![
         RgbaPixel::new(158, 36, 222, 0),  // #9e24de
@@ -341,7 +401,9 @@ fn write_minimal_nine_pixels() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_one,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_one);
     xcf.add_layers(&layers);
@@ -355,7 +417,7 @@ fn write_minimal_nine_pixels() -> Result<(), Error> {
 #[test]
 fn write_minimal_one_pixel_two_layers() -> Result<(), Error> {
     let mut xcf = XcfCreator::new(11, 1, 1, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let pixels_layer_one = vec![
@@ -375,7 +437,9 @@ fn write_minimal_one_pixel_two_layers() -> Result<(), Error> {
         },
         name: "Layer1".to_string(),
         pixels: pixels_layer_one,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_one);
     let pixels_layer_two = vec![
@@ -395,7 +459,9 @@ fn write_minimal_one_pixel_two_layers() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -409,7 +475,7 @@ fn write_minimal_one_pixel_two_layers() -> Result<(), Error> {
 #[test]
 fn write_minimal_9x3() -> Result<(), Error> {
    let mut xcf = XcfCreator::new(11, 9, 3, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let pixels_layer_two = vec![
@@ -458,7 +524,9 @@ fn write_minimal_9x3() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -473,7 +541,7 @@ fn write_minimal_9x3() -> Result<(), Error> {
 fn write_minimal_9x9() -> Result<(), Error> {
     let height = 9;
     let mut xcf = XcfCreator::new(11, 9, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -502,7 +570,9 @@ fn write_minimal_9x9() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -517,7 +587,7 @@ fn write_minimal_9x9() -> Result<(), Error> {
 fn write_minimal_9x15_diff_bytes() -> Result<(), Error> {
     let height = 15;
     let mut xcf = XcfCreator::new(11, 9, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -546,7 +616,9 @@ fn write_minimal_9x15_diff_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -562,7 +634,7 @@ fn write_minimal_9x15_same_bytes() -> Result<(), Error> {
     let width = 9;
     let height = 15;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -585,7 +657,9 @@ fn write_minimal_9x15_same_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -601,7 +675,7 @@ fn write_minimal_9x65_same_bytes() -> Result<(), Error> {
     let width = 9;
     let height = 65;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -624,7 +698,9 @@ fn write_minimal_9x65_same_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -641,7 +717,7 @@ fn write_minimal_9x138_same_bytes() -> Result<(), Error> {
     let width = 9;
     let height = 138;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -664,7 +740,9 @@ fn write_minimal_9x138_same_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -679,7 +757,7 @@ fn write_minimal_138x138_same_bytes() -> Result<(), Error> {
     let width = 138;
     let height = 138;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -702,7 +780,9 @@ fn write_minimal_138x138_same_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -718,7 +798,7 @@ fn write_minimal_12x1_diff_bytes() -> Result<(), Error> {
     let width = 12;
     let height = 1;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -744,7 +824,9 @@ fn write_minimal_12x1_diff_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -760,7 +842,7 @@ fn write_minimal_12x12_diff_bytes() -> Result<(), Error> {
     let width = 12;
     let height = 12;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -786,7 +868,9 @@ fn write_minimal_12x12_diff_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -802,7 +886,7 @@ fn write_minimal_7x1_diff_bytes() -> Result<(), Error> {
     let width = 7;
     let height = 1;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -829,7 +913,9 @@ fn write_minimal_7x1_diff_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -845,7 +931,7 @@ fn write_minimal_9x9_diff_bytes() -> Result<(), Error> {
     let width = 9;
     let height = 9;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -874,7 +960,9 @@ fn write_minimal_9x9_diff_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -890,7 +978,7 @@ fn write_minimal_36x36_bytes() -> Result<(), Error> {
     let width = 36;
     let height = 36;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_two = vec![];
@@ -919,7 +1007,9 @@ fn write_minimal_36x36_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_two,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_two);
     xcf.add_layers(&layers);
@@ -935,7 +1025,7 @@ fn write_minimal_1x32_bytes() -> Result<(), Error> {
     let width = 1;
     let height = 32;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_one = vec![];
@@ -966,7 +1056,9 @@ fn write_minimal_1x32_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_one,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_one);
     xcf.add_layers(&layers);
@@ -982,7 +1074,7 @@ fn write_minimal_1x64_bytes() -> Result<(), Error> {
     let width = 1;
     let height = 64;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_one = vec![];
@@ -1011,7 +1103,9 @@ fn write_minimal_1x64_bytes() -> Result<(), Error> {
         },
         name: "Background".to_string(),
         pixels: pixels_layer_one,
-        properties: vec![],
+        properties: gimp_layer_properties(),
+        native: None,
+        mask: None,
     };
     layers.push(layer_one);
     xcf.add_layers(&layers);
@@ -1037,6 +1131,8 @@ fn create_layer(width: u32, height: u32, pixel_data: Vec<RgbaPixel>) -> Layer {
             pixels: pixel_data,
         },
         properties: vec![],
+        native: None,
+        mask: None,
     }
 }
 
@@ -1045,7 +1141,7 @@ fn write_minimal_128x129_diff_bytes() -> Result<(), Error> {
     let width = 128;
     let height = 129;
     let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
-    xcf.add_properties(&vec![]);
+    xcf.add_properties(&gimp_properties());
     let mut layers = vec![];
 
     let mut pixels_layer_one = vec![];
@@ -1060,7 +1156,8 @@ fn write_minimal_128x129_diff_bytes() -> Result<(), Error> {
         pixels_layer_one.push(RgbaPixel::new(255, 255, 255, 0)); //rgb(255, 255, 255)
     }
 
-    let layer_one = create_layer(width, height, pixels_layer_one);
+    let mut layer_one = create_layer(width, height, pixels_layer_one);
+    layer_one.properties = gimp_layer_properties();
     layers.push(layer_one);
     xcf.add_layers(&layers);
     let xcf_file = create_file("minimal_128x129_diff_pixels.xcf", &mut xcf)?;
@@ -1069,3 +1166,218 @@ fn write_minimal_128x129_diff_bytes() -> Result<(), Error> {
     assert_hash(xcf_file.2.to_str().expect(""), file_hash);
     Ok(())
 }
+
+fn compressed_xcf(
+    width: u32,
+    height: u32,
+    pixels: Vec<RgbaPixel>,
+    compression: XcfCompression,
+    level: u32,
+) -> XcfCreator {
+    let mut xcf = XcfCreator::new(11, width, height, ColorType::Rgb);
+    xcf.set_compression_level(level).unwrap();
+    xcf.add_properties(&vec![Property {
+        kind: PropertyIdentifier::PropCompression,
+        length: 1,
+        payload: PropertyPayload::Compression(compression),
+    }]);
+    xcf.add_layers(&vec![create_layer(width, height, pixels)]);
+    xcf
+}
+
+fn zlib_xcf(width: u32, height: u32, pixels: Vec<RgbaPixel>, level: u32) -> XcfCreator {
+    compressed_xcf(width, height, pixels, XcfCompression::Zlib, level)
+}
+
+#[test]
+fn write_zlib_compression() -> Result<(), Error> {
+    let (width, height) = (130, 70);
+    let pixels: Vec<_> = (0..width * height)
+        .map(|i| RgbaPixel::new((i % 7) as u8, (i / 130) as u8, 200, 255))
+        .collect();
+    let mut xcf = zlib_xcf(width, height, pixels.clone(), 9);
+    let xcf_file = create_file("zlib_130x70.xcf", &mut xcf)?;
+
+    let image = Xcf::open(xcf_file.1)?;
+    assert_eq!(image.header.compression(), XcfCompression::Zlib);
+    assert_eq!(image.layers[0].pixels.pixels, pixels);
+
+    // the smallest tiles are bigger once compressed than they are raw
+    let mut xcf = zlib_xcf(1, 1, vec![RgbaPixel::new(158, 36, 222, 255)], 6);
+    let xcf_file = create_file("zlib_1x1.xcf", &mut xcf)?;
+    let image = Xcf::open(xcf_file.1)?;
+    assert_eq!(
+        image.layers[0].pixel(0, 0),
+        Some(RgbaPixel::new(158, 36, 222, 255))
+    );
+    Ok(())
+}
+
+#[test]
+fn write_zlib_compression_level() {
+    let (width, height) = (128, 128);
+    let pixels: Vec<_> = (0..width * height)
+        .map(|i| RgbaPixel::new((i % 3) as u8, (i % 5) as u8, (i % 11) as u8, 255))
+        .collect();
+    let fastest = zlib_xcf(width, height, pixels.clone(), 0);
+    let smallest = zlib_xcf(width, height, pixels.clone(), 9);
+    assert!(smallest.data.len() < fastest.data.len());
+
+    let mut rle = XcfCreator::new(11, width, height, ColorType::Rgb);
+    rle.add_properties(&gimp_properties());
+    rle.add_layers(&vec![create_layer(width, height, pixels)]);
+    assert!(smallest.data.len() < rle.data.len());
+
+    assert!(matches!(
+        rle.set_compression_level(10),
+        Err(Error::NotSupported)
+    ));
+}
+
+#[test]
+fn write_without_compression() -> Result<(), Error> {
+    let (width, height) = (70, 65);
+    let pixels: Vec<_> = (0..width * height)
+        .map(|i| RgbaPixel::new((i % 70) as u8, (i / 70) as u8, 100, 255))
+        .collect();
+    let mut xcf = compressed_xcf(width, height, pixels.clone(), XcfCompression::None, 6);
+    let raw_file = create_file("uncompressed_70x65.xcf", &mut xcf)?;
+    let mut xcf = compressed_xcf(width, height, pixels.clone(), XcfCompression::Rle, 6);
+    let rle_file = create_file("rle_70x65.xcf", &mut xcf)?;
+
+    let raw = Xcf::open(raw_file.1)?;
+    let rle = Xcf::open(rle_file.1)?;
+    assert_eq!(raw.header.compression(), XcfCompression::None);
+    assert_eq!(raw.layers[0].pixels.pixels, pixels);
+    assert_eq!(raw.layers[0].pixels, rle.layers[0].pixels);
+    Ok(())
+}
+
+#[test]
+fn write_v10_layers() -> Result<(), Error> {
+    let (width, height) = (70, 65);
+    let pixels: Vec<_> = (0..width * height)
+        .map(|i| RgbaPixel::new((i % 70) as u8, (i / 70) as u8, 100, 255))
+        .collect();
+    for compression in [
+        XcfCompression::None,
+        XcfCompression::Rle,
+        XcfCompression::Zlib,
+    ] {
+        let mut xcf = XcfCreator::new(10, width, height, ColorType::Rgb);
+        xcf.add_properties(&vec![Property {
+            kind: PropertyIdentifier::PropCompression,
+            length: 1,
+            payload: PropertyPayload::Compression(compression),
+        }]);
+        let mut top = create_layer(width, height, pixels.clone());
+        top.name = "Top".to_string();
+        xcf.add_layers(&vec![top, create_layer(width, height, pixels.clone())]);
+        let xcf_file = create_file("v10_70x65.xcf", &mut xcf)?;
+
+        let image = Xcf::open(xcf_file.1)?;
+        assert_eq!(image.header.version.num(), 10);
+        assert_eq!(image.header.compression(), compression);
+        let names: Vec<_> = image.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Top", "Background"]);
+        for layer in &image.layers {
+            assert_eq!(layer.pixels.pixels, pixels);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn write_precision_of_old_versions() -> Result<(), Error> {
+    for version in 1..=10 {
+        let mut xcf = XcfCreator::new(version, 1, 1, ColorType::Rgb);
+        xcf.add_properties(&gimp_properties());
+        xcf.add_layers(&vec![violet_background()]);
+        let image = Xcf::load(Cursor::new(xcf.data))?;
+        assert_eq!(image.header.version.num(), version);
+        assert_eq!(image.header.precision, Precision::NonLinearU8);
+        assert_eq!(
+            image.layers[0].pixel(0, 0),
+            Some(RgbaPixel::new(158, 36, 222, 255))
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn build_with_defaults() -> Result<(), Error> {
+    let pixels = vec![RgbaPixel::new(158, 36, 222, 255); 4];
+    let image = XcfBuilder::new(2, 2)
+        .layer(create_layer(2, 2, pixels.clone()))
+        .build();
+    let mut data = vec![];
+    image.write_to(&mut data)?;
+    let saved = Xcf::load(Cursor::new(data))?;
+
+    assert_eq!(saved.header, image.header);
+    assert_eq!(saved.header.version.num(), 11);
+    assert_eq!(saved.header.precision, Precision::NonLinearU8);
+    assert_eq!(saved.header.compression(), XcfCompression::Rle);
+    assert_eq!(
+        saved.header.properties[1].payload,
+        PropertyPayload::ResolutionProperty(ResolutionProperty {
+            xres: 300.0,
+            yres: 300.0
+        })
+    );
+    // nothing else, no comment in particular
+    assert_eq!(saved.header.properties.len(), 2);
+    assert!(saved.layers[0].properties.is_empty());
+    assert!(saved.layers[0].is_visible());
+    assert_eq!(saved.layers[0].pixels.pixels, pixels);
+    Ok(())
+}
+
+#[test]
+fn build_with_overrides() -> Result<(), Error> {
+    let pixels: Vec<_> = (0..70 * 65)
+        .map(|i| RgbaPixel::new((i % 70) as u8, (i / 70) as u8, 100, 255))
+        .collect();
+    let mut top = create_layer(70, 65, pixels.clone());
+    top.name = "Top".to_string();
+    top.set_opacity(0.5);
+    let image = XcfBuilder::new(70, 65)
+        .precision(Precision::LinearF16)
+        .compression(XcfCompression::Zlib)
+        .resolution(72.0, 96.0)
+        .comment("Made by a script")
+        .layer(top)
+        .layer(create_layer(70, 65, pixels.clone()))
+        .build();
+    let mut data = vec![];
+    image.write_to(&mut data)?;
+    let saved = Xcf::load(Cursor::new(data))?;
+
+    assert_eq!(saved.header, image.header);
+    assert_eq!(saved.header.precision, Precision::LinearF16);
+    assert_eq!(saved.header.compression(), XcfCompression::Zlib);
+    assert_eq!(
+        saved.header.properties[2].payload,
+        PropertyPayload::Parasites(vec![ParasiteProperty {
+            name: "gimp-comment".to_string(),
+            flags: 1,
+            data: "Made by a script".to_string(),
+        }])
+    );
+    assert_eq!(saved.layers[0].opacity(), 0.5);
+    for layer in &saved.layers {
+        assert_eq!(layer.pixels.pixels, pixels);
+    }
+
+    // old versions have no 64-bit pointers
+    let image = XcfBuilder::new(70, 65)
+        .version(10)
+        .layer(create_layer(70, 65, pixels.clone()))
+        .build();
+    let mut data = vec![];
+    image.write_to(&mut data)?;
+    let saved = Xcf::load(Cursor::new(data))?;
+    assert_eq!(saved.header.version.num(), 10);
+    assert_eq!(saved.layers[0].pixels.pixels, pixels);
+    Ok(())
+}
@@ -0,0 +1,268 @@
+mod common;
+
+use std::fs::File;
+use std::io::Cursor;
+
+use common::{
+    TestChannel, TestImage, TestLayer, apply_mask, composite_mode, composite_space, float_opacity,
+    group, item_path, mode, offsets, visible,
+};
+
+use xcf_rs::data::{error::Error, rect::Rect, xcf::Xcf};
+
+fn gray_background(width: u32, height: u32) -> TestLayer {
+    TestLayer {
+        width,
+        height,
+        kind: 0,
+        name: "Background",
+        properties: vec![],
+        bpp: 3,
+        data: vec![100; (width * height * 3) as usize],
+        mask: None,
+    }
+}
+
+fn flatten(image: &TestImage) -> Result<Vec<[u8; 4]>, Error> {
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+    Ok(raw_image.flatten().pixels.iter().map(|p| p.0).collect())
+}
+
+#[test]
+fn flatten_layers_bottom_to_top() -> Result<(), Error> {
+    let mut image = TestImage::new(4, 1);
+    image.layers = vec![
+        // layers are stored from top to bottom
+        TestLayer::rgba("Hidden", 4, 1, &[[255, 0, 0, 255]; 4]).with(visible(false)),
+        TestLayer::rgba("Sprite", 2, 1, &[[255, 0, 0, 255], [0, 0, 255, 255]])
+            .with(offsets(1, 0))
+            .with(float_opacity(0.5)),
+        gray_background(4, 1),
+    ];
+
+    assert_eq!(
+        flatten(&image)?,
+        [
+            [100, 100, 100, 255],
+            // composited in linear light
+            [198, 71, 71, 255],
+            [71, 71, 198, 255],
+            [100, 100, 100, 255],
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn flatten_transparent_and_off_canvas_layers() -> Result<(), Error> {
+    let mut image = TestImage::new(2, 2);
+    image.layers = vec![
+        // hangs off the top left corner, only its last pixel is on the canvas
+        TestLayer::rgba("Corner", 2, 2, &[[0, 255, 0, 255]; 4]).with(offsets(-1, -1)),
+        TestLayer::rgba("Glass", 2, 2, &[[0, 0, 255, 51]; 4]),
+    ];
+
+    assert_eq!(
+        flatten(&image)?,
+        [
+            [0, 255, 0, 255],
+            [0, 0, 255, 51],
+            [0, 0, 255, 51],
+            [0, 0, 255, 51],
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn flatten_with_layer_mask() -> Result<(), Error> {
+    let mut image = TestImage::new(2, 1);
+    let mut sprite = TestLayer::rgba("Sprite", 2, 1, &[[255, 0, 0, 255]; 2]);
+    sprite.mask = Some(TestChannel {
+        width: 2,
+        height: 1,
+        name: "Sprite mask",
+        properties: vec![],
+        bpp: 1,
+        data: vec![0, 255],
+    });
+    image.layers = vec![sprite, gray_background(2, 1)];
+
+    // the mask is ignored until it is applied
+    assert_eq!(flatten(&image)?, [[255, 0, 0, 255], [255, 0, 0, 255]]);
+
+    image.layers[0].properties.push(apply_mask());
+    assert_eq!(flatten(&image)?, [[100, 100, 100, 255], [255, 0, 0, 255]]);
+
+    Ok(())
+}
+
+#[test]
+fn flatten_groups() -> Result<(), Error> {
+    let mut image = TestImage::new(2, 1);
+    image.layers = vec![
+        TestLayer::rgba("Group", 2, 1, &[[0; 4]; 2])
+            .with(group())
+            .with(float_opacity(0.5)),
+        TestLayer::rgba("Red", 1, 1, &[[255, 0, 0, 255]]).with(item_path(&[0, 0])),
+        TestLayer::rgba("Blue", 2, 1, &[[0, 0, 255, 255]; 2]).with(item_path(&[0, 1])),
+        TestLayer::rgba("Hidden group", 2, 1, &[[0; 4]; 2])
+            .with(group())
+            .with(visible(false)),
+        TestLayer::rgba("Green", 2, 1, &[[0, 255, 0, 255]; 2]).with(item_path(&[1, 0])),
+        gray_background(2, 1),
+    ];
+
+    // the children of the group are composited before the opacity of the group applies
+    assert_eq!(flatten(&image)?, [[198, 71, 71, 255], [71, 71, 198, 255]]);
+
+    Ok(())
+}
+
+#[test]
+fn flatten_region() -> Result<(), Error> {
+    let mut image = TestImage::new(4, 1);
+    image.layers = vec![
+        TestLayer::rgba("Sprite", 1, 1, &[[255, 0, 0, 255]]).with(offsets(2, 0)),
+        gray_background(4, 1),
+    ];
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    let region = raw_image.flatten_region(Rect::new(1, 0, 2, 1));
+    assert_eq!((region.width, region.height), (2, 1));
+    assert_eq!(region.pixels[0].0, [100, 100, 100, 255]);
+    assert_eq!(region.pixels[1].0, [255, 0, 0, 255]);
+
+    // outside of the canvas is transparent
+    let region = raw_image.flatten_region(Rect::new(3, -1, 2, 2));
+    let pixels: Vec<_> = region.pixels.iter().map(|p| p.0).collect();
+    assert_eq!(pixels, [[0; 4], [0; 4], [100, 100, 100, 255], [0; 4]]);
+
+    assert_eq!(
+        raw_image.flatten(),
+        raw_image.flatten_region(Rect::new(0, 0, 4, 1))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn flatten_with_layer_modes() -> Result<(), Error> {
+    let mut image = TestImage::new(3, 1);
+    image.layers = vec![
+        // erase
+        TestLayer::rgba("Eraser", 1, 1, &[[0, 0, 0, 255]])
+            .with(offsets(2, 0))
+            .with(float_opacity(0.5))
+            .with(mode(58)),
+        // multiply
+        TestLayer::rgba("Shadow", 2, 1, &[[255, 0, 0, 255], [128, 128, 128, 255]]).with(mode(30)),
+        gray_background(3, 1),
+    ];
+
+    assert_eq!(
+        flatten(&image)?,
+        [[100, 0, 0, 255], [46, 46, 46, 255], [100, 100, 100, 128]]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn flatten_pass_through_groups() -> Result<(), Error> {
+    let mut image = TestImage::new(1, 1);
+    image.layers = vec![
+        TestLayer::rgba("Group", 1, 1, &[[0; 4]])
+            .with(group())
+            .with(float_opacity(0.5)),
+        // multiply
+        TestLayer::rgba("Red", 1, 1, &[[255, 0, 0, 255]])
+            .with(item_path(&[0, 0]))
+            .with(mode(30)),
+        gray_background(1, 1),
+    ];
+
+    // the multiply layer is clipped to the empty backdrop inside of the group
+    assert_eq!(flatten(&image)?, [[100, 100, 100, 255]]);
+
+    // pass through
+    image.layers[0].properties.push(mode(61));
+    assert_eq!(flatten(&image)?, [[100, 71, 71, 255]]);
+
+    Ok(())
+}
+
+#[test]
+fn flatten_in_perceptual_rgb() -> Result<(), Error> {
+    let mut image = TestImage::new(2, 1);
+    image.layers = vec![
+        // legacy normal
+        TestLayer::rgba("Legacy", 1, 1, &[[255, 0, 0, 255]])
+            .with(float_opacity(0.5))
+            .with(mode(0)),
+        // normal in perceptual RGB
+        TestLayer::rgba("Perceptual", 1, 1, &[[255, 0, 0, 255]])
+            .with(offsets(1, 0))
+            .with(float_opacity(0.5))
+            .with(composite_space(2)),
+        gray_background(2, 1),
+    ];
+
+    assert_eq!(flatten(&image)?, [[178, 50, 50, 255], [178, 50, 50, 255]]);
+
+    Ok(())
+}
+
+#[test]
+fn flatten_with_composite_modes() -> Result<(), Error> {
+    let flatten_with = |composite: u32| {
+        let mut image = TestImage::new(1, 1);
+        image.layers = vec![
+            TestLayer::rgba("Red", 1, 1, &[[255, 0, 0, 128]]).with(composite_mode(composite)),
+            TestLayer::rgba("Blue", 1, 1, &[[0, 0, 255, 128]]),
+        ];
+        flatten(&image)
+    };
+
+    // union is the default of normal mode
+    assert_eq!(flatten_with(u32::MAX)?, flatten_with(1)?);
+    assert_eq!(flatten_with(1)?, [[213, 0, 156, 192]]);
+    // clip to backdrop
+    assert_eq!(flatten_with(2)?, [[188, 0, 187, 128]]);
+    // clip to layer
+    assert_eq!(flatten_with(3)?, [[255, 0, 0, 128]]);
+    // intersection
+    assert_eq!(flatten_with(4)?, [[255, 0, 0, 64]]);
+
+    Ok(())
+}
+
+#[test]
+fn flatten_like_gimp() -> Result<(), Error> {
+    let mut raw_image = Xcf::open("tests/samples/parsing/512x512-base-with-alpha.xcf")?;
+    // exported by GIMP with its semi-transparent layer shown
+    raw_image.layer_mut("Layer 1").unwrap().set_visible(true);
+    let decoder = png::Decoder::new(File::open(
+        "tests/samples/parsing/512x512-base-with-alpha.png",
+    )?);
+    let mut reader = decoder.read_info().unwrap();
+    let mut expected = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut expected).unwrap();
+    assert_eq!((info.width, info.height), (512, 512));
+    let channels = info.color_type.samples();
+
+    let flattened = raw_image.flatten();
+    for (pixel, expected) in flattened.pixels.iter().zip(expected.chunks(channels)) {
+        for c in 0..channels {
+            assert!(
+                pixel.0[c].abs_diff(expected[c]) <= 1,
+                "{:?} != {expected:?}",
+                pixel.0
+            );
+        }
+    }
+
+    Ok(())
+}
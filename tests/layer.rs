@@ -0,0 +1,185 @@
+use std::io::Cursor;
+
+use xcf_rs::blend::Compositing;
+use xcf_rs::create::XcfCreator;
+use xcf_rs::data::{
+    color::{ColorTag, ColorType},
+    error::Error,
+    layer::Layer,
+    mode::{LayerColorSpace, LayerCompositeMode, LayerMode},
+    pixeldata::PixelData,
+    property::{Property, PropertyIdentifier, PropertyPayload},
+    rgba::RgbaPixel,
+    xcf::{Xcf, XcfCompression},
+};
+use xcf_rs::{LayerColorType, LayerColorValue};
+
+fn create_layer(properties: Vec<Property>) -> Layer {
+    Layer {
+        width: 1,
+        height: 1,
+        kind: LayerColorType {
+            kind: LayerColorValue::Rgba,
+            alpha: true,
+        },
+        name: "Background".to_string(),
+        properties,
+        pixels: PixelData {
+            width: 1,
+            height: 1,
+            pixels: vec![RgbaPixel::new(158, 36, 222, 255)],
+        },
+        native: None,
+        mask: None,
+    }
+}
+
+#[test]
+fn read_layer_state() -> Result<(), Error> {
+    let raw_image = Xcf::open("tests/samples/parsing/512x512-base-with-alpha.xcf")?;
+
+    let layer = raw_image.layer("Layer 1").unwrap();
+    assert_eq!(layer.opacity(), 1.0);
+    assert!(!layer.is_visible());
+    assert_eq!(layer.offsets(), (0, 0));
+    assert_eq!(layer.mode(), LayerMode::Normal);
+    assert_eq!(layer.color_tag(), ColorTag::None);
+    assert!(!layer.lock_content());
+    assert!(!layer.lock_alpha());
+    assert!(!layer.lock_position());
+    assert!(!layer.lock_visibility());
+    assert!(raw_image.layer("Layer 2").unwrap().is_visible());
+
+    Ok(())
+}
+
+#[test]
+fn layer_state_defaults() {
+    let layer = create_layer(vec![]);
+    assert_eq!(layer.opacity(), 1.0);
+    assert!(layer.is_visible());
+    assert_eq!(layer.offsets(), (0, 0));
+    assert_eq!(layer.mode(), LayerMode::Normal);
+    assert_eq!(layer.color_tag(), ColorTag::None);
+
+    // GIMP 2.8 only knew the 8-bit opacity
+    let layer = create_layer(vec![Property {
+        kind: PropertyIdentifier::PropOpacity,
+        length: 4,
+        payload: PropertyPayload::OpacityLayer(RgbaPixel([0, 0, 0, 51])),
+    }]);
+    assert_eq!(layer.opacity(), 0.2);
+}
+
+#[test]
+fn write_layer_state() -> Result<(), Error> {
+    let mut layer = create_layer(vec![Property {
+        kind: PropertyIdentifier::PropVisible,
+        length: 4,
+        payload: PropertyPayload::VisibleLayer(1),
+    }]);
+    layer.set_opacity(0.5);
+    layer.set_visible(false);
+    layer.set_offsets(3, 4);
+    layer.set_mode(LayerMode::Multiply);
+    layer.set_color_tag(ColorTag::Orange);
+    layer.set_lock_content(true);
+    layer.set_lock_alpha(true);
+    layer.set_lock_position(true);
+    layer.set_lock_visibility(true);
+    // the existing property is updated rather than duplicated
+    assert_eq!(
+        layer
+            .properties
+            .iter()
+            .filter(|p| p.kind == PropertyIdentifier::PropVisible)
+            .count(),
+        1
+    );
+
+    let mut xcf = XcfCreator::new(11, 1, 1, ColorType::Rgb);
+    xcf.add_properties(&vec![Property {
+        kind: PropertyIdentifier::PropCompression,
+        length: 1,
+        payload: PropertyPayload::Compression(XcfCompression::Rle),
+    }]);
+    xcf.add_layers(&vec![layer]);
+    let raw_image = Xcf::load(Cursor::new(xcf.data))?;
+
+    let layer = raw_image.layer("Background").unwrap();
+    assert_eq!(layer.opacity(), 0.5);
+    assert_eq!(
+        layer.properties[1].payload,
+        PropertyPayload::OpacityLayer(RgbaPixel([0, 0, 0, 128]))
+    );
+    assert!(!layer.is_visible());
+    assert_eq!(layer.offsets(), (3, 4));
+    assert_eq!(layer.mode(), LayerMode::Multiply);
+    assert_eq!(layer.color_tag(), ColorTag::Orange);
+    assert!(layer.lock_content());
+    assert!(layer.lock_alpha());
+    assert!(layer.lock_position());
+    assert!(layer.lock_visibility());
+    assert_eq!(layer.pixel(0, 0).unwrap().0, [158, 36, 222, 255]);
+
+    Ok(())
+}
+
+#[test]
+fn layer_compositing() -> Result<(), Error> {
+    let raw_image = Xcf::open("tests/samples/parsing/512x512-base-with-alpha.xcf")?;
+
+    // GIMP leaves them on auto, which is linear RGB and union for normal mode
+    let layer = raw_image.layer("Layer 1").unwrap();
+    assert_eq!(layer.blend_space(), LayerColorSpace::Auto);
+    assert_eq!(layer.composite_space(), LayerColorSpace::Auto);
+    assert_eq!(layer.composite_mode(), LayerCompositeMode::Auto);
+    assert_eq!(layer.compositing(), Compositing::new(LayerMode::Normal));
+    assert_eq!(
+        layer.compositing(),
+        Compositing {
+            mode: LayerMode::Normal,
+            blend_space: LayerColorSpace::RgbLinear,
+            composite_space: LayerColorSpace::RgbLinear,
+            composite_mode: LayerCompositeMode::Union,
+        }
+    );
+
+    let mut layer = create_layer(vec![]);
+    layer.set_mode(LayerMode::MultiplyLegacy);
+    assert_eq!(
+        layer.compositing(),
+        Compositing {
+            mode: LayerMode::MultiplyLegacy,
+            blend_space: LayerColorSpace::RgbPerceptual,
+            composite_space: LayerColorSpace::RgbPerceptual,
+            composite_mode: LayerCompositeMode::ClipToBackdrop,
+        }
+    );
+
+    layer.set_mode(LayerMode::LchColor);
+    layer.set_composite_space(LayerColorSpace::RgbPerceptual);
+    layer.set_composite_mode(LayerCompositeMode::Intersection);
+    let mut xcf = XcfCreator::new(11, 1, 1, ColorType::Rgb);
+    xcf.add_properties(&vec![Property {
+        kind: PropertyIdentifier::PropCompression,
+        length: 1,
+        payload: PropertyPayload::Compression(XcfCompression::Rle),
+    }]);
+    xcf.add_layers(&vec![layer]);
+    let raw_image = Xcf::load(Cursor::new(xcf.data))?;
+
+    let layer = raw_image.layer("Background").unwrap();
+    assert_eq!(layer.blend_space(), LayerColorSpace::Auto);
+    assert_eq!(
+        layer.compositing(),
+        Compositing {
+            mode: LayerMode::LchColor,
+            blend_space: LayerColorSpace::Lab,
+            composite_space: LayerColorSpace::RgbPerceptual,
+            composite_mode: LayerCompositeMode::Intersection,
+        }
+    );
+
+    Ok(())
+}
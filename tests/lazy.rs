@@ -0,0 +1,134 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{TestChannel, TestImage, TestLayer, group};
+
+use xcf_rs::data::{error::Error, rect::Rect, rgba::RgbaPixel, xcf::Xcf};
+
+#[test]
+fn lazy_layers() -> Result<(), Error> {
+    let path = "tests/samples/parsing/246x6734-odd-size-odd-layer.xcf";
+    let lazy_image = Xcf::open_lazy(path)?;
+
+    assert_eq!(lazy_image.dimensions(), (246, 6734));
+    let names: Vec<_> = lazy_image.layers.iter().map(|l| l.name.as_str()).collect();
+    assert_eq!(names, ["Layer 2", "Background"]);
+
+    // only the tile pointers are read
+    let background = lazy_image.layer("Background").unwrap();
+    let hierarchy = background.hierarchy.as_ref().unwrap();
+    assert_eq!((hierarchy.width, hierarchy.height), (246, 6734));
+    assert_eq!((hierarchy.tiles_x(), hierarchy.tiles_y()), (4, 106));
+    assert_eq!(hierarchy.tile_pointers.len(), 4 * 106 + 1);
+    assert_eq!(hierarchy.tile_pointers.last(), Some(&0));
+
+    let raw_image = Xcf::open(path)?;
+    let layer = lazy_image.load_layer(background)?;
+    assert_eq!(Some(layer), raw_image.layer("Background"));
+    // the pixels are decoded once
+    assert!(std::ptr::eq(layer, lazy_image.load_layer(background)?));
+
+    let layers = lazy_image.into_xcf()?.layers;
+    assert_eq!(layers, raw_image.layers);
+
+    Ok(())
+}
+
+#[test]
+fn lazy_masks_and_channels() -> Result<(), Error> {
+    let mut image = TestImage::new(2, 1);
+    let mut sprite = TestLayer::rgba("Sprite", 2, 1, &[[255, 0, 0, 255]; 2]);
+    sprite.mask = Some(TestChannel {
+        width: 2,
+        height: 1,
+        name: "Sprite mask",
+        properties: vec![],
+        bpp: 1,
+        data: vec![0, 255],
+    });
+    image.layers = vec![
+        TestLayer::rgba("Group", 2, 1, &[[0; 4]; 2]).with(group()),
+        sprite,
+    ];
+    image.channels = vec![TestChannel {
+        width: 2,
+        height: 1,
+        name: "Selection",
+        properties: vec![],
+        bpp: 1,
+        data: vec![7, 8],
+    }];
+    let lazy_image = Xcf::load_lazy(Cursor::new(image.to_bytes()))?;
+
+    // groups have no pixels to decode
+    let group = lazy_image.layer("Group").unwrap();
+    assert_eq!(group.hierarchy, None);
+    assert_eq!(lazy_image.load_layer(group)?.pixel(0, 0), None);
+
+    let sprite = lazy_image.layer("Sprite").unwrap();
+    assert_eq!(sprite.mask.as_ref().unwrap().name, "Sprite mask");
+    let mask = lazy_image.load_layer(sprite)?.mask.as_ref().unwrap();
+    assert_eq!(mask.pixels, [0, 255]);
+
+    let selection = lazy_image.channel("Selection").unwrap();
+    assert_eq!(lazy_image.load_channel(selection)?.pixels, [7, 8]);
+
+    Ok(())
+}
+
+#[test]
+fn lazy_layer_regions() -> Result<(), Error> {
+    for path in [
+        "tests/samples/parsing/246x6734-odd-size-odd-layer.xcf",
+        // zlib
+        "tests/samples/parsing/1024x1024-better-compression.xcf",
+    ] {
+        let raw_image = Xcf::open(path)?;
+        let lazy_image = Xcf::open_lazy(path)?;
+        let handle = &lazy_image.layers[lazy_image.layers.len() - 1];
+        let layer = raw_image.layer(&handle.name).unwrap();
+
+        // across four tiles
+        let region = lazy_image.layer_region(handle, Rect::new(60, 120, 10, 20))?;
+        assert_eq!((region.width, region.height), (10, 20));
+        assert_eq!(
+            region.raw_sub_rgba_buffer(0, 0, 10, 20),
+            layer.raw_sub_rgba_buffer(60, 120, 10, 20)
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn lazy_layer_regions_outside_of_the_layer() -> Result<(), Error> {
+    let mut image = TestImage::new(2, 2);
+    let mut background = TestLayer::rgba("Background", 2, 2, &[[0; 4]; 4]);
+    // RGB
+    background.kind = 0;
+    background.bpp = 3;
+    background.data = vec![10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120];
+    image.layers = vec![background];
+    let lazy_image = Xcf::load_lazy(Cursor::new(image.to_bytes()))?;
+    let handle = lazy_image.layer("Background").unwrap();
+
+    let region = lazy_image.layer_region(handle, Rect::new(1, -1, 2, 3))?;
+    let transparent = RgbaPixel::new(0, 0, 0, 0);
+    assert_eq!(
+        region.pixels,
+        [
+            transparent,
+            transparent,
+            RgbaPixel::new(40, 50, 60, 255),
+            transparent,
+            RgbaPixel::new(100, 110, 120, 255),
+            transparent,
+        ]
+    );
+
+    let region = lazy_image.layer_region(handle, Rect::new(5, 5, 1, 1))?;
+    assert_eq!(region.pixels, [transparent]);
+
+    Ok(())
+}
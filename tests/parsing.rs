@@ -1,4 +1,26 @@
-use xcf_rs::data::{error::Error, xcf::Xcf};
+mod common;
+
+use std::io::Cursor;
+
+use common::{TestChannel, TestImage, TestLayer, item_path};
+
+use xcf_rs::LayerColorValue;
+use xcf_rs::create::XcfCreator;
+use xcf_rs::data::{
+    color::ColorType,
+    error::Error,
+    hierarchy::Hierarchy,
+    pixeldata::Components,
+    precision::Precision,
+    property::{
+        GuideProperty, ParasiteProperty, Property, PropertyIdentifier, PropertyPayload,
+        ResolutionProperty,
+    },
+    rect::Rect,
+    rgba::RgbaPixel,
+    version::Version,
+    xcf::{Xcf, XcfCompression},
+};
 
 #[test]
 fn read_1x1_violet_legacy() -> Result<(), Error> {
@@ -38,8 +60,19 @@ fn read_245x6734_odd_size_odd_layer() -> Result<(), Error> {
     assert_eq!(raw_image.layers[1].dimensions(), (246, 6734));
 
     assert_eq!(raw_image.layers[0].name, "Layer 2");
-    // TODO: check layer offset
+    assert_eq!(raw_image.layers[0].offsets(), (72, 1717));
     assert_eq!(raw_image.layers[0].dimensions(), (200, 200));
+    assert_eq!(raw_image.layers[0].bounds(), Rect::new(72, 1717, 200, 200));
+    // the layer goes past the right edge of the canvas
+    assert_eq!(
+        raw_image.layer_bounds_on_canvas(&raw_image.layers[0]),
+        Some(Rect::new(72, 1717, 174, 200))
+    );
+    assert_eq!(
+        raw_image.layers[0].canvas_pixel(72, 1717),
+        raw_image.layers[0].pixel(0, 0)
+    );
+    assert_eq!(raw_image.layers[0].canvas_pixel(71, 1717), None);
 
     Ok(())
 }
@@ -87,25 +120,24 @@ fn mini() -> Result<(), Error> {
     assert_eq!(raw_image.dimensions(), (1, 1));
     assert_eq!(raw_image.layers.len(), 1);
 
+    // a linear 16-bit grayscale image
+    assert_eq!(raw_image.header.precision, Precision::LinearU16);
+    let layer = &raw_image.layers[0];
+    assert_eq!(layer.native_pixel(0, 0), Some(&[56, 52][..]));
+    assert_eq!(layer.components(), Some(Components::U16(vec![14388])));
+    assert_eq!(layer.pixel(0, 0).unwrap().0, [56, 56, 56, 255]);
+
     Ok(())
 }
 
 #[test]
-#[ignore]
 fn read_1024x1024_better_compression() -> Result<(), Error> {
     let raw_image = Xcf::open("tests/samples/parsing/1024x1024-better-compression.xcf")?;
 
-    //assert_eq!(raw_image.header.version.num(), 11);
-    //assert_eq!(raw_image.dimensions(), (1024, 1024));
+    assert_eq!(raw_image.header.version.num(), 11);
+    assert_eq!(raw_image.header.compression(), XcfCompression::Zlib);
+    assert_eq!(raw_image.dimensions(), (512, 512));
 
-    let raw_pixel = raw_image
-    .layer("Background")
-    .unwrap()
-    .pixels
-    .pixel(220, 203)
-    .unwrap();
-    println!("raw_pixel : {:?}", raw_pixel);
-    // TODO: check bg does not have alpha
     assert_eq!(
         raw_image
             .layer("Background")
@@ -114,7 +146,7 @@ fn read_1024x1024_better_compression() -> Result<(), Error> {
             .pixel(220, 203)
             .unwrap()
             .0,
-        [125, 125, 125, 255], // TODO: the alpha in this test is wrong - is it 125?
+        [215, 194, 78, 255]
     );
 
     assert_eq!(
@@ -125,8 +157,883 @@ fn read_1024x1024_better_compression() -> Result<(), Error> {
             .pixel(220, 203)
             .unwrap()
             .0,
-        [215, 194, 78, 255]
+        [125, 125, 125, 131]
+    );
+
+    // same image saved with RLE compression
+    let rle_image = Xcf::open("tests/samples/parsing/512x512-base-with-alpha.xcf")?;
+    for name in ["Layer 1", "Layer 2"] {
+        assert_eq!(
+            raw_image.layer(name).unwrap().pixels,
+            rle_image.layer(name).unwrap().pixels
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn read_xcf10_without_compression_property() -> Result<(), Error> {
+    let raw_image = Xcf::open("tests/samples/minimal_xcf10.xcf")?;
+
+    assert_eq!(raw_image.header.compression(), XcfCompression::None);
+    assert_eq!(
+        raw_image.layers[0].pixel(0, 0).unwrap().0,
+        [158, 36, 222, 255]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn read_uncompressed_tiles() -> Result<(), Error> {
+    let pixel = |x: u32, y: u32| [x as u8, y as u8, (x + y) as u8, 255 - x as u8];
+    let mut image = TestImage::new(70, 66);
+    image.layers.push(TestLayer {
+        width: 70,
+        height: 66,
+        kind: 1,
+        name: "Layer",
+        properties: vec![],
+        bpp: 4,
+        data: (0..66)
+            .flat_map(|y| (0..70).flat_map(move |x| pixel(x, y)))
+            .collect(),
+        mask: None,
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    assert_eq!(raw_image.header.compression(), XcfCompression::None);
+    let layer = raw_image.layer("Layer").unwrap();
+    for (x, y) in [(0, 0), (63, 0), (64, 0), (69, 3), (5, 64), (69, 65)] {
+        assert_eq!(layer.pixel(x, y).unwrap().0, pixel(x, y));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn read_channels() -> Result<(), Error> {
+    let mut image = TestImage::new(66, 2);
+    image.layers.push(TestLayer {
+        width: 66,
+        height: 2,
+        kind: 0,
+        name: "Background",
+        properties: vec![],
+        bpp: 3,
+        data: vec![128; 66 * 2 * 3],
+        mask: None,
+    });
+    image.channels.push(TestChannel {
+        width: 66,
+        height: 2,
+        name: "Roughness",
+        properties: vec![
+            (6, vec![0, 0, 0, 128]),
+            (33, 0.5f32.to_be_bytes().to_vec()),
+            (8, vec![0, 0, 0, 0]),
+            (14, vec![0, 0, 0, 1]),
+            (16, vec![255, 0, 128]),
+            (20, vec![0, 0, 0, 7]),
+        ],
+        bpp: 1,
+        data: (0..66 * 2).map(|i| i as u8).collect(),
+    });
+    image.channels.push(TestChannel {
+        width: 66,
+        height: 2,
+        name: "AO",
+        properties: vec![(6, vec![0, 0, 0, 255]), (8, vec![0, 0, 0, 1])],
+        bpp: 1,
+        data: vec![42; 66 * 2],
+    });
+    image.channels.push(TestChannel {
+        width: 66,
+        height: 2,
+        name: "Plain",
+        properties: vec![],
+        bpp: 1,
+        data: vec![0; 66 * 2],
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    assert_eq!(raw_image.layers.len(), 1);
+    assert_eq!(raw_image.channels.len(), 3);
+
+    let roughness = raw_image.channel("Roughness").unwrap();
+    assert_eq!(roughness.dimensions(), (66, 2));
+    assert_eq!(roughness.pixel(0, 0), Some(0));
+    assert_eq!(roughness.pixel(65, 0), Some(65));
+    assert_eq!(roughness.pixel(1, 1), Some(67));
+    assert_eq!(roughness.pixel(66, 0), None);
+    assert_eq!(roughness.opacity(), 0.5);
+    assert!(!roughness.is_visible());
+    assert!(roughness.show_masked());
+    assert_eq!(roughness.color(), [255, 0, 128]);
+    assert_eq!(roughness.tattoo(), Some(7));
+
+    let ao = raw_image.channel("AO").unwrap();
+    assert!(ao.pixels.iter().all(|&v| v == 42));
+    assert_eq!(ao.opacity(), 1.0);
+    assert!(ao.is_visible());
+    assert!(!ao.show_masked());
+    assert_eq!(ao.tattoo(), None);
+
+    // like layers, channels without properties are visible and opaque
+    let plain = raw_image.channel("Plain").unwrap();
+    assert!(plain.is_visible());
+    assert_eq!(plain.opacity(), 1.0);
+
+    assert!(raw_image.channel("Height").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn read_layer_mask() -> Result<(), Error> {
+    let mut image = TestImage::new(2, 1);
+    image.layers.push(TestLayer {
+        width: 2,
+        height: 1,
+        kind: 1,
+        name: "Sprite",
+        properties: vec![
+            (11, vec![0, 0, 0, 1]),
+            (12, vec![0, 0, 0, 0]),
+            (13, vec![0, 0, 0, 1]),
+        ],
+        bpp: 4,
+        data: vec![10, 20, 30, 255, 40, 50, 60, 200],
+        mask: Some(TestChannel {
+            width: 2,
+            height: 1,
+            name: "Sprite mask",
+            properties: vec![(8, vec![0, 0, 0, 1])],
+            bpp: 1,
+            data: vec![0, 255],
+        }),
+    });
+    image.layers.push(TestLayer {
+        width: 2,
+        height: 1,
+        kind: 0,
+        name: "Background",
+        properties: vec![],
+        bpp: 3,
+        data: vec![0; 6],
+        mask: None,
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    let sprite = raw_image.layer("Sprite").unwrap();
+    let mask = sprite.mask.as_ref().unwrap();
+    assert_eq!(mask.name, "Sprite mask");
+    assert_eq!(mask.pixels, vec![0, 255]);
+    assert!(mask.is_visible());
+    assert!(sprite.apply_mask());
+    assert!(!sprite.edit_mask());
+    assert!(sprite.show_mask());
+
+    assert_eq!(sprite.pixel(0, 0).unwrap().0, [10, 20, 30, 255]);
+    assert_eq!(sprite.masked_pixel(0, 0).unwrap().0, [10, 20, 30, 0]);
+    assert_eq!(sprite.masked_pixel(1, 0).unwrap().0, [40, 50, 60, 200]);
+
+    let background = raw_image.layer("Background").unwrap();
+    assert!(background.mask.is_none());
+    assert!(!background.apply_mask());
+    assert_eq!(background.masked_pixel(0, 0), background.pixel(0, 0));
+
+    Ok(())
+}
+
+#[test]
+fn read_grayscale() -> Result<(), Error> {
+    let mut image = TestImage::new(65, 1);
+    image.color_type = 1;
+    image.layers.push(TestLayer {
+        width: 65,
+        height: 1,
+        kind: 3,
+        name: "Mask",
+        properties: vec![],
+        bpp: 2,
+        data: (0..65).flat_map(|x| [x as u8, 255 - x as u8]).collect(),
+        mask: None,
+    });
+    image.layers.push(TestLayer {
+        width: 65,
+        height: 1,
+        kind: 2,
+        name: "Heightmap",
+        properties: vec![],
+        bpp: 1,
+        data: (0..65).map(|x| 2 * x as u8).collect(),
+        mask: None,
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    assert_eq!(raw_image.header.color_type, ColorType::Grayscale);
+
+    let mask = raw_image.layer("Mask").unwrap();
+    assert_eq!(mask.kind.kind, LayerColorValue::GrayscaleWithAlpha);
+    assert!(mask.kind.alpha);
+    assert_eq!(mask.native_pixel(64, 0), Some(&[64, 191][..]));
+    assert_eq!(mask.pixel(64, 0).unwrap().0, [64, 64, 64, 191]);
+
+    let heightmap = raw_image.layer("Heightmap").unwrap();
+    assert_eq!(heightmap.kind.kind, LayerColorValue::Grayscale);
+    assert!(!heightmap.kind.alpha);
+    assert_eq!(heightmap.native_pixel(10, 0), Some(&[20][..]));
+    assert_eq!(heightmap.pixel(10, 0).unwrap().0, [20, 20, 20, 255]);
+    assert_eq!(heightmap.raw_rgba_buffer().len(), 65);
+
+    Ok(())
+}
+
+fn colormap(colors: &[[u8; 3]]) -> (u32, Vec<u8>) {
+    let mut payload = (colors.len() as u32).to_be_bytes().to_vec();
+    payload.extend(colors.iter().flatten());
+    (1, payload)
+}
+
+#[test]
+fn read_indexed() -> Result<(), Error> {
+    let mut image = TestImage::new(3, 1);
+    image.color_type = 2;
+    image
+        .properties
+        .push(colormap(&[[255, 0, 0], [0, 255, 0], [0, 0, 255]]));
+    image.layers.push(TestLayer {
+        width: 3,
+        height: 1,
+        kind: 5,
+        name: "Sprite",
+        properties: vec![],
+        bpp: 2,
+        data: vec![2, 255, 0, 0, 1, 128],
+        mask: None,
+    });
+    image.layers.push(TestLayer {
+        width: 3,
+        height: 1,
+        kind: 4,
+        name: "Background",
+        properties: vec![],
+        bpp: 1,
+        data: vec![1, 1, 7],
+        mask: None,
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    assert_eq!(raw_image.header.color_type, ColorType::Indexed);
+    let palette = raw_image.palette().unwrap();
+    assert_eq!(palette.len(), 3);
+    assert_eq!(palette.colors[1], [0, 255, 0]);
+
+    let sprite = raw_image.layer("Sprite").unwrap();
+    assert_eq!(sprite.kind.kind, LayerColorValue::IndexedWithAlpha);
+    assert_eq!(sprite.palette_index(0, 0), Some(2));
+    assert_eq!(sprite.raw_index_buffer(), Some(vec![2, 0, 1]));
+    assert_eq!(sprite.pixel(0, 0).unwrap().0, [0, 0, 255, 255]);
+    assert_eq!(sprite.pixel(1, 0).unwrap().0, [255, 0, 0, 0]);
+    assert_eq!(sprite.pixel(2, 0).unwrap().0, [0, 255, 0, 128]);
+
+    let background = raw_image.layer("Background").unwrap();
+    assert_eq!(background.kind.kind, LayerColorValue::Indexed);
+    assert_eq!(background.raw_index_buffer(), Some(vec![1, 1, 7]));
+    assert_eq!(background.pixel(0, 0).unwrap().0, [0, 255, 0, 255]);
+    // out of the palette
+    assert_eq!(background.pixel(2, 0).unwrap().0, [0, 0, 0, 255]);
+
+    Ok(())
+}
+
+#[test]
+fn read_padded_property() -> Result<(), Error> {
+    let mut image = TestImage::new(1, 1);
+    image.channels.push(TestChannel {
+        width: 1,
+        height: 1,
+        name: "Padded",
+        // the visibility is longer than its value
+        properties: vec![(8, vec![0, 0, 0, 0, 0, 0, 0, 0]), (20, vec![0, 0, 0, 3])],
+        bpp: 1,
+        data: vec![9],
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    let padded = &raw_image.channels[0];
+    assert!(!padded.is_visible());
+    assert_eq!(padded.tattoo(), Some(3));
+    assert_eq!(padded.pixel(0, 0), Some(9));
+
+    Ok(())
+}
+
+#[test]
+fn read_colormap_with_the_length_gimp_writes() -> Result<(), Error> {
+    let mut image = TestImage::new(1, 1);
+    image.color_type = 2;
+    image
+        .properties
+        .push(colormap(&[[255, 0, 0], [0, 255, 0], [0, 0, 255]]));
+    image.properties.push((20, vec![0, 0, 0, 5]));
+    image.layers.push(TestLayer {
+        width: 1,
+        height: 1,
+        kind: 4,
+        name: "Background",
+        properties: vec![],
+        bpp: 1,
+        data: vec![2],
+        mask: None,
+    });
+    let mut data = image.to_bytes();
+    // GIMP stores `colors + 4` rather than the size of the palette, the colormap follows the
+    // signature, the size of the image, its color type, its precision and its compression
+    let length = 14 + 4 * 4 + 9 + 4;
+    assert_eq!(data[length - 4..length], 1u32.to_be_bytes());
+    data[length..length + 4].copy_from_slice(&7u32.to_be_bytes());
+    let raw_image = Xcf::load(Cursor::new(data))?;
+
+    assert_eq!(
+        raw_image.palette().unwrap().colors,
+        vec![[255, 0, 0], [0, 255, 0], [0, 0, 255]]
+    );
+    assert_eq!(
+        raw_image.header.properties[2].payload,
+        PropertyPayload::Tatoo(5)
+    );
+    let background = raw_image.layer("Background").unwrap();
+    assert_eq!(background.pixel(0, 0).unwrap().0, [0, 0, 255, 255]);
+
+    Ok(())
+}
+
+#[test]
+fn read_indexed_version_0_colormap() -> Result<(), Error> {
+    let mut image = TestImage::new(2, 1);
+    image.version = 0;
+    image.color_type = 2;
+    // version 0 wrote one byte per color, and a length of `colors + 4`
+    image.properties.push((1, vec![0, 0, 0, 4, 9, 9, 9, 9]));
+    image.layers.push(TestLayer {
+        width: 2,
+        height: 1,
+        kind: 4,
+        name: "Background",
+        properties: vec![],
+        bpp: 1,
+        data: vec![0, 3],
+        mask: None,
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    assert_eq!(raw_image.header.version.num(), 0);
+    assert_eq!(
+        raw_image.palette().unwrap().colors,
+        vec![[0; 3], [1; 3], [2; 3], [3; 3]]
+    );
+    let background = raw_image.layer("Background").unwrap();
+    assert_eq!(background.pixel(1, 0).unwrap().0, [3, 3, 3, 255]);
+
+    Ok(())
+}
+
+#[test]
+fn read_float_precisions() -> Result<(), Error> {
+    let mut image = TestImage::new(2, 1);
+    // linear 32-bit float
+    image.precision = 600;
+    let values: [f32; 8] = [0.0, 0.5, 1.0, 1.0, 4.0, -1.0, 0.25, 0.5];
+    image.layers.push(TestLayer {
+        width: 2,
+        height: 1,
+        kind: 1,
+        name: "Lighting",
+        properties: vec![],
+        bpp: 16,
+        data: values.iter().flat_map(|v| v.to_be_bytes()).collect(),
+        mask: None,
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    assert_eq!(raw_image.header.precision, Precision::LinearF32);
+    let layer = raw_image.layer("Lighting").unwrap();
+    assert_eq!(layer.components(), Some(Components::F32(values.to_vec())));
+    assert_eq!(layer.pixel(0, 0).unwrap().0, [0, 128, 255, 255]);
+    // out of range values are clamped
+    assert_eq!(layer.pixel(1, 0).unwrap().0, [255, 0, 64, 128]);
+
+    // half floats
+    image.precision = 500;
+    image.layers[0].bpp = 8;
+    image.layers[0].data = [
+        0x0000u16, 0x3800, 0x3c00, 0x3c00, 0x4400, 0xbc00, 0x3400, 0x3800,
+    ]
+    .iter()
+    .flat_map(|v| v.to_be_bytes())
+    .collect();
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    assert_eq!(raw_image.header.precision, Precision::LinearF16);
+    let layer = raw_image.layer("Lighting").unwrap();
+    assert_eq!(layer.components().unwrap().to_f32(), values.to_vec());
+    assert_eq!(layer.pixel(1, 0).unwrap().0, [255, 0, 64, 128]);
+
+    Ok(())
+}
+
+#[test]
+fn read_high_bit_depth_integers() -> Result<(), Error> {
+    let mut image = TestImage::new(1, 1);
+    // gamma 32-bit integer
+    image.precision = 350;
+    image.layers.push(TestLayer {
+        width: 1,
+        height: 1,
+        kind: 3,
+        name: "Displacement",
+        properties: vec![],
+        bpp: 8,
+        data: vec![0x80, 0, 0, 0, 0xff, 0xff, 0xff, 0xff],
+        mask: None,
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    let layer = raw_image.layer("Displacement").unwrap();
+    assert_eq!(
+        layer.components(),
+        Some(Components::U32(vec![0x8000_0000, u32::MAX]))
     );
+    assert_eq!(layer.pixel(0, 0).unwrap().0, [128, 128, 128, 255]);
+
+    // the size of the pixels does not match the precision
+    image.precision = 250;
+    assert!(Xcf::load(Cursor::new(image.to_bytes())).is_err());
 
     Ok(())
 }
+
+/// Components that repeat in long and short runs, and differ in between.
+fn runs_and_noise(count: usize) -> Vec<u16> {
+    (0..count)
+        .map(|i| match i / 4 % 300 {
+            0..=199 => 1000,
+            200..=259 => (i / 16) as u16,
+            _ => (i * 7919) as u16,
+        })
+        .collect()
+}
+
+#[test]
+fn read_compressed_high_bit_depths() -> Result<(), Error> {
+    let values = runs_and_noise(70 * 66 * 4);
+    let floats: Vec<f32> = values.iter().map(|v| *v as f32 / 65535.0).collect();
+    // gamma 16-bit integer and linear 32-bit float RGBA, over several tiles
+    let cases = [
+        (
+            250,
+            values
+                .iter()
+                .flat_map(|v| v.to_be_bytes())
+                .collect::<Vec<_>>(),
+            Components::U16(values.clone()),
+        ),
+        (
+            600,
+            floats.iter().flat_map(|v| v.to_be_bytes()).collect(),
+            Components::F32(floats.clone()),
+        ),
+    ];
+    for (precision, data, components) in cases {
+        let mut image = TestImage::new(70, 66);
+        image.precision = precision;
+        image.layers.push(TestLayer {
+            width: 70,
+            height: 66,
+            kind: 1,
+            name: "Background",
+            properties: vec![],
+            bpp: data.len() as u32 / (70 * 66),
+            data,
+            mask: None,
+        });
+        for compression in [XcfCompression::Rle, XcfCompression::Zlib] {
+            image.compression = compression.to_u8();
+            let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+            assert_eq!(raw_image.header.compression(), compression);
+            assert_eq!(raw_image.layers[0].components(), Some(components.clone()));
+        }
+    }
+    Ok(())
+}
+
+fn layer_in_group(name: &'static str, properties: Vec<(u32, Vec<u8>)>) -> TestLayer {
+    TestLayer {
+        width: 1,
+        height: 1,
+        kind: 1,
+        name,
+        properties,
+        bpp: 4,
+        data: vec![10, 20, 30, 255],
+        mask: None,
+    }
+}
+
+#[test]
+fn read_layer_groups() -> Result<(), Error> {
+    let group = (29, vec![]);
+    let expanded = (31, vec![0, 0, 0, 1]);
+    let mut image = TestImage::new(1, 1);
+    image.layers = vec![
+        layer_in_group("Characters", vec![group.clone(), expanded.clone()]),
+        layer_in_group("Hero", vec![group.clone(), item_path(&[0, 0])]),
+        layer_in_group("Arm", vec![item_path(&[0, 0, 0])]),
+        layer_in_group("Leg", vec![item_path(&[0, 0, 1])]),
+        layer_in_group("Villain", vec![item_path(&[0, 1])]),
+        layer_in_group("Background", vec![]),
+    ];
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    // the layers stay in the order of the file
+    assert_eq!(raw_image.layers.len(), 6);
+    let characters = raw_image.layer("Characters").unwrap();
+    assert!(characters.is_group());
+    assert!(characters.is_expanded());
+    assert_eq!(characters.item_path(), None);
+    // groups have no pixels of their own
+    assert_eq!(characters.pixel(0, 0), None);
+    assert!(!raw_image.layer("Hero").unwrap().is_expanded());
+    assert_eq!(
+        raw_image.layer("Leg").unwrap().item_path(),
+        Some(&[0, 0, 1][..])
+    );
+
+    let tree = raw_image.layer_tree();
+    let names = |nodes: &[xcf_rs::data::tree::LayerNode]| {
+        nodes
+            .iter()
+            .map(|n| n.layer.name.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(&tree), ["Characters", "Background"]);
+    assert_eq!(names(&tree[0].children), ["Hero", "Villain"]);
+    assert_eq!(names(&tree[0].children[0].children), ["Arm", "Leg"]);
+    assert_eq!(
+        tree[0]
+            .descendants()
+            .map(|n| n.layer.name.as_str())
+            .collect::<Vec<_>>(),
+        ["Hero", "Arm", "Leg", "Villain"]
+    );
+    assert_eq!(tree[0].find("Hero/Leg").unwrap().index, 3);
+
+    let arm = raw_image.layer_by_path("Characters/Hero/Arm").unwrap();
+    assert_eq!(arm.name, "Arm");
+    assert_eq!(arm.pixel(0, 0).unwrap().0, [10, 20, 30, 255]);
+    assert!(raw_image.layer_by_path("Characters/Arm").is_none());
+    assert!(raw_image.layer_by_path("Background").is_some());
+
+    // a path to a group that isn't there puts the layer at the top level
+    image
+        .layers
+        .insert(5, layer_in_group("Stray", vec![item_path(&[0, 0, 5, 0])]));
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+    let tree = raw_image.layer_tree();
+    assert_eq!(names(&tree), ["Characters", "Stray", "Background"]);
+    assert_eq!(names(&tree[0].children[0].children), ["Arm", "Leg"]);
+    assert!(raw_image.layer_by_path("Stray").is_some());
+
+    Ok(())
+}
+
+#[test]
+fn preserve_unknown_properties() -> Result<(), Error> {
+    let mut image = TestImage::new(1, 1);
+    image.properties.push((1234, vec![1, 2, 3]));
+    image.layers.push(TestLayer {
+        width: 1,
+        height: 1,
+        kind: 1,
+        name: "Background",
+        properties: vec![(999, vec![4, 5, 6, 7, 8])],
+        bpp: 4,
+        data: vec![10, 20, 30, 255],
+        mask: None,
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    let unknown = Property {
+        kind: PropertyIdentifier::Unknown(1234),
+        length: 3,
+        payload: PropertyPayload::Unknown(vec![1, 2, 3]),
+    };
+    assert!(raw_image.header.properties.contains(&unknown));
+    let layer_unknown = &raw_image.layer("Background").unwrap().properties[0];
+    assert_eq!(layer_unknown.kind, PropertyIdentifier::Unknown(999));
+    assert_eq!(layer_unknown.kind.to_u32(), 999);
+
+    // save it again, the unknown properties are written back as they were read
+    let mut xcf = XcfCreator::new(11, 1, 1, ColorType::Rgb);
+    xcf.add_properties(&vec![
+        Property {
+            kind: PropertyIdentifier::PropCompression,
+            length: 1,
+            payload: PropertyPayload::Compression(XcfCompression::Rle),
+        },
+        unknown,
+    ]);
+    xcf.add_layers(&raw_image.layers);
+    let saved_image = Xcf::load(Cursor::new(xcf.data))?;
+
+    assert_eq!(
+        saved_image.header.properties[1].kind,
+        PropertyIdentifier::Unknown(1234)
+    );
+    assert_eq!(
+        saved_image.header.properties[1].payload,
+        PropertyPayload::Unknown(vec![1, 2, 3])
+    );
+    let saved_layer = saved_image.layer("Background").unwrap();
+    assert_eq!(saved_layer.properties, raw_image.layers[0].properties);
+    assert_eq!(saved_layer.pixel(0, 0).unwrap().0, [10, 20, 30, 255]);
+
+    Ok(())
+}
+
+#[test]
+fn read_typed_properties() -> Result<(), Error> {
+    let raw_image = Xcf::open("tests/samples/parsing/1x1-violet-with-comment.xcf")?;
+
+    let payloads: Vec<_> = raw_image
+        .header
+        .properties
+        .iter()
+        .map(|p| &p.payload)
+        .collect();
+    assert_eq!(
+        payloads[0],
+        &PropertyPayload::Compression(XcfCompression::Rle)
+    );
+    assert_eq!(
+        payloads[1],
+        &PropertyPayload::ResolutionProperty(ResolutionProperty {
+            xres: 300.0,
+            yres: 300.0
+        })
+    );
+    assert_eq!(payloads[2], &PropertyPayload::Tatoo(2));
+    assert_eq!(payloads[3], &PropertyPayload::Unit(1));
+    match payloads[4] {
+        PropertyPayload::Parasites(parasites) => {
+            assert_eq!(parasites.len(), 2);
+            assert_eq!(
+                parasites[0],
+                ParasiteProperty {
+                    name: "gimp-comment".to_string(),
+                    flags: 1,
+                    data: "Test Comment".to_string(),
+                }
+            );
+            assert_eq!(parasites[1].name, "gimp-image-grid");
+        }
+        _ => panic!("parasites are not decoded"),
+    }
+
+    let layer = raw_image.layer("Background").unwrap();
+    let payloads: Vec<_> = layer.properties.iter().map(|p| &p.payload).collect();
+    assert_eq!(
+        payloads,
+        [
+            &PropertyPayload::ActiveLayer(),
+            &PropertyPayload::OpacityLayer(RgbaPixel::new(0, 0, 0, 255)),
+            &PropertyPayload::FloatOpacityLayer(1.0),
+            &PropertyPayload::VisibleLayer(1),
+            &PropertyPayload::LinkedLayer(0),
+            &PropertyPayload::ColorTagLayer(0),
+            &PropertyPayload::LockContentLayer(0),
+            &PropertyPayload::LockAlphaLayer(0),
+            &PropertyPayload::LockPositionLayer(0),
+            &PropertyPayload::ApplyMaskLayer(0),
+            &PropertyPayload::EditMaskLayer(0),
+            &PropertyPayload::ShowMaskLayer(0),
+            &PropertyPayload::OffsetsLayer(0, 0),
+            &PropertyPayload::ModeLayer(28),
+            &PropertyPayload::BlendSpaceLayer(0),
+            &PropertyPayload::CompositeSpaceLayer(u32::MAX),
+            &PropertyPayload::CompositeModeLayer(u32::MAX),
+            &PropertyPayload::Tatoo(2),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn read_guides_and_channel_properties() -> Result<(), Error> {
+    let mut image = TestImage::new(1, 1);
+    // a vertical guide at 10 and an horizontal one at -3
+    image
+        .properties
+        .push((18, vec![0, 0, 0, 10, 2, 255, 255, 255, 253, 1]));
+    // a binary parasite is kept as it is
+    let mut parasite = vec![0, 0, 0, 4, b'i', b'c', b'c', 0, 0, 0, 0, 1, 0, 0, 0, 2];
+    parasite.extend_from_slice(&[0xff, 0x00]);
+    image.properties.push((21, parasite.clone()));
+    image.channels.push(TestChannel {
+        width: 1,
+        height: 1,
+        name: "Selection",
+        properties: vec![
+            (3, vec![]),
+            (
+                38,
+                [0.5f32, 0.25, 1.0]
+                    .iter()
+                    .flat_map(|v| v.to_be_bytes())
+                    .collect(),
+            ),
+        ],
+        bpp: 1,
+        data: vec![255],
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    assert_eq!(
+        raw_image.header.properties[1].payload,
+        PropertyPayload::Guides(vec![
+            GuideProperty {
+                position: 10,
+                orientation: 2
+            },
+            GuideProperty {
+                position: -3,
+                orientation: 1
+            },
+        ])
+    );
+    assert_eq!(
+        raw_image.header.properties[2].payload,
+        PropertyPayload::Unknown(parasite)
+    );
+    let channel = raw_image.channel("Selection").unwrap();
+    assert_eq!(
+        channel.properties[0].payload,
+        PropertyPayload::ActiveChannel()
+    );
+    assert_eq!(
+        channel.properties[1].payload,
+        PropertyPayload::FloatColorChannel([0.5, 0.25, 1.0])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn read_negative_offsets() -> Result<(), Error> {
+    let mut image = TestImage::new(8, 8);
+    let mut offsets = (-5i32).to_be_bytes().to_vec();
+    offsets.extend_from_slice(&(-2i32).to_be_bytes());
+    image.layers.push(TestLayer {
+        width: 10,
+        height: 10,
+        kind: 3,
+        name: "Sprite",
+        properties: vec![(15, offsets)],
+        bpp: 2,
+        data: (0..100u8).flat_map(|v| [v, 255]).collect(),
+        mask: None,
+    });
+    let raw_image = Xcf::load(Cursor::new(image.to_bytes()))?;
+
+    let layer = raw_image.layer("Sprite").unwrap();
+    assert_eq!(layer.offsets(), (-5, -2));
+    assert_eq!(layer.bounds(), Rect::new(-5, -2, 10, 10));
+    assert_eq!(
+        raw_image.layer_bounds_on_canvas(layer),
+        Some(Rect::new(0, 0, 5, 8))
+    );
+    // the top left corner of the canvas is the pixel (5, 2) of the layer
+    assert_eq!(layer.canvas_pixel(0, 0).unwrap().0, [25, 25, 25, 255]);
+    assert_eq!(layer.canvas_pixel(-5, -2).unwrap().0, [0, 0, 0, 255]);
+    assert_eq!(layer.canvas_pixel(5, 0), None);
+
+    Ok(())
+}
+
+/// A hierarchy of `width` by `height` pixels, its level right after it but none of its tiles.
+fn hierarchy_without_tiles(width: u32, height: u32) -> Vec<u8> {
+    let mut data = vec![];
+    for value in [width, height, 4] {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+    data.extend_from_slice(&20u64.to_be_bytes());
+    data.extend_from_slice(&width.to_be_bytes());
+    data.extend_from_slice(&height.to_be_bytes());
+    data
+}
+
+#[test]
+fn read_hierarchy_of_huge_size() {
+    // the number of tiles doesn't fit in 32 bits
+    let data = hierarchy_without_tiles(u32::MAX, u32::MAX);
+    assert!(matches!(
+        Hierarchy::parse(Cursor::new(data), Version(11)),
+        Err(Error::InvalidFormat)
+    ));
+
+    // millions of tiles the file doesn't have
+    let data = hierarchy_without_tiles(200_000, 200_000);
+    assert!(matches!(
+        Hierarchy::parse(Cursor::new(data), Version(11)),
+        Err(Error::Io(_))
+    ));
+}
+
+/// Change the size stored before the name of an item, leaving the size of its pixels as is.
+fn resize_item(data: &mut [u8], name: &str, offset: usize, width: u32, height: u32) {
+    let name = format!("{name}\0");
+    let at = data
+        .windows(name.len())
+        .position(|w| w == name.as_bytes())
+        .unwrap();
+    let size = at - 4 - offset - 8;
+    data[size..size + 4].copy_from_slice(&width.to_be_bytes());
+    data[size + 4..size + 8].copy_from_slice(&height.to_be_bytes());
+}
+
+#[test]
+fn read_items_bigger_than_their_pixels() {
+    let mut image = TestImage::new(4, 4);
+    image
+        .layers
+        .push(TestLayer::rgba("Background", 1, 1, &[[1, 2, 3, 255]]));
+    image.channels.push(TestChannel {
+        width: 1,
+        height: 1,
+        name: "Roughness",
+        properties: vec![],
+        bpp: 1,
+        data: vec![9],
+    });
+    let data = image.to_bytes();
+    assert!(Xcf::load(Cursor::new(data.clone())).is_ok());
+
+    // the kind of a layer is stored between its size and its name
+    let mut layer = data.clone();
+    resize_item(&mut layer, "Background", 4, 4, 4);
+    assert!(matches!(
+        Xcf::load(Cursor::new(layer)),
+        Err(Error::InvalidFormat)
+    ));
+
+    let mut channel = data;
+    resize_item(&mut channel, "Roughness", 0, 4, 4);
+    assert!(matches!(
+        Xcf::load(Cursor::new(channel)),
+        Err(Error::InvalidFormat)
+    ));
+}
@@ -1,4 +1,8 @@
-use xcf_rs::rle::rle_decompress;
+use xcf_rs::rle;
+
+fn rle_decompress(data: &[u8]) -> Vec<u8> {
+    rle::rle_decompress(data).unwrap()
+}
 
 #[test]
 fn rle_decompression_four_pixels() {
@@ -626,3 +630,31 @@ fn rle_decompression_512x512_pixels() {
         raw
     );
 }
+
+#[test]
+fn rle_decode_interleaved_channel() {
+    let mut pixels = vec![0; 8];
+    // a run of 3 identical bytes then 1 different byte, every other byte
+    assert_eq!(
+        rle::rle_decode(&[2, 7, 255, 9, 42], &mut pixels[1..], 2).unwrap(),
+        4
+    );
+    assert_eq!(pixels, vec![0, 7, 0, 7, 0, 7, 0, 9]);
+}
+
+#[test]
+fn rle_decode_truncated_stream() {
+    let mut plane = vec![0; 4];
+    assert!(rle::rle_decode(&[], &mut plane, 1).is_err());
+    assert!(rle::rle_decode(&[252, 1, 2], &mut plane, 1).is_err());
+    assert!(rle::rle_decode(&[127, 0], &mut plane, 1).is_err());
+    assert!(rle::rle_decompress(&[128, 0, 3, 1, 2]).is_err());
+}
+
+#[test]
+fn rle_decode_overlong_run() {
+    let mut plane = vec![0; 4];
+    assert!(rle::rle_decode(&[4, 1], &mut plane, 1).is_err());
+    assert!(rle::rle_decode(&[251, 1, 2, 3, 4, 5], &mut plane, 1).is_err());
+    assert!(rle::rle_decode(&[127, 1, 0, 1], &mut plane, 1).is_err());
+}
@@ -0,0 +1,231 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{TestChannel, TestImage, TestLayer, apply_mask, group, item_path, offsets};
+
+use xcf_rs::data::{
+    error::Error,
+    property::{ParasiteProperty, Property, PropertyIdentifier, PropertyPayload},
+    rgba::RgbaPixel,
+    xcf::{Xcf, XcfCompression},
+};
+
+fn round_trip(image: &Xcf) -> Result<Xcf, Error> {
+    let mut data = vec![];
+    image.write_to(&mut data)?;
+    Xcf::load(Cursor::new(data))
+}
+
+fn assert_same(image: &Xcf, saved: &Xcf) {
+    assert_eq!(image.header, saved.header);
+    assert_eq!(image.layers, saved.layers);
+    assert_eq!(image.channels, saved.channels);
+}
+
+fn set_compression(image: &mut Xcf, compression: XcfCompression) {
+    for property in &mut image.header.properties {
+        if let PropertyPayload::Compression(value) = &mut property.payload {
+            *value = compression;
+        }
+    }
+}
+
+#[test]
+fn save_samples() -> Result<(), Error> {
+    let samples = [
+        "tests/samples/parsing/1024x1024-better-compression.xcf",
+        "tests/samples/parsing/1x1-violet-legacy.xcf",
+        "tests/samples/parsing/1x1-violet-with-comment.xcf",
+        "tests/samples/parsing/246x6734-odd-size-odd-layer.xcf",
+        "tests/samples/parsing/512x512-base-with-alpha.xcf",
+        "tests/samples/parsing/512x512-yellow-base-cloud-layer-empty-layer.xcf",
+        "tests/samples/parsing/mini.xcf",
+        "tests/samples/minimal_xcf11_properties.xcf",
+        "tests/samples/minimal_one_pixel_two_layers.xcf",
+    ];
+    for path in samples {
+        let image = Xcf::open(path)?;
+        let saved = round_trip(&image)?;
+        assert_same(&image, &saved);
+
+        // saving what was saved gives the same file
+        let (mut first, mut second) = (vec![], vec![]);
+        image.write_to(&mut first)?;
+        saved.write_to(&mut second)?;
+        assert_eq!(first, second, "{path}");
+    }
+    Ok(())
+}
+
+#[test]
+fn save_with_every_compression() -> Result<(), Error> {
+    let mut image = Xcf::open("tests/samples/parsing/246x6734-odd-size-odd-layer.xcf")?;
+    for compression in [
+        XcfCompression::None,
+        XcfCompression::Rle,
+        XcfCompression::Zlib,
+    ] {
+        set_compression(&mut image, compression);
+        let saved = round_trip(&image)?;
+        assert_eq!(saved.header.compression(), compression);
+        assert_same(&image, &saved);
+    }
+    set_compression(&mut image, XcfCompression::Fractal);
+    assert!(matches!(round_trip(&image), Err(Error::NotSupported)));
+    Ok(())
+}
+
+#[test]
+fn save_with_compression_level() -> Result<(), Error> {
+    let mut image = Xcf::open("tests/samples/parsing/1024x1024-better-compression.xcf")?;
+    set_compression(&mut image, XcfCompression::Zlib);
+    let (mut fastest, mut smallest) = (vec![], vec![]);
+    image.write_to_with_level(&mut fastest, 0)?;
+    image.write_to_with_level(&mut smallest, 9)?;
+    assert!(smallest.len() < fastest.len());
+    assert_same(&image, &Xcf::load(Cursor::new(fastest))?);
+    assert_same(&image, &Xcf::load(Cursor::new(smallest))?);
+
+    assert!(matches!(
+        image.write_to_with_level(vec![], 10),
+        Err(Error::NotSupported)
+    ));
+    Ok(())
+}
+
+#[test]
+fn save_masks_channels_and_unknown_properties() -> Result<(), Error> {
+    let mut image = TestImage::new(3, 2);
+    image.precision = 250; // 16-bit gamma integer
+    image.properties.push((1234, vec![1, 2, 3]));
+    let mut sprite = TestLayer {
+        width: 2,
+        height: 2,
+        kind: 3,
+        name: "Sprite",
+        properties: vec![offsets(1, 0), apply_mask(), (999, vec![4, 5])],
+        bpp: 4,
+        data: (0..16).collect(),
+        mask: None,
+    };
+    sprite.mask = Some(TestChannel {
+        width: 2,
+        height: 2,
+        name: "Sprite mask",
+        properties: vec![],
+        bpp: 2,
+        data: vec![255, 255, 0, 1, 128, 0, 0, 0],
+    });
+    image.layers = vec![
+        TestLayer::rgba("Group", 3, 2, &[[0; 4]; 6]).with(group()),
+        sprite.with(item_path(&[0, 0])),
+    ];
+    image.layers[0].kind = 3;
+    image.layers[0].bpp = 4;
+    image.channels = vec![TestChannel {
+        width: 3,
+        height: 2,
+        name: "Selection",
+        properties: vec![(4, vec![])],
+        bpp: 2,
+        data: (0..12).collect(),
+    }];
+    let mut image = Xcf::load(Cursor::new(image.to_bytes()))?;
+    image.header.properties.push(Property {
+        kind: PropertyIdentifier::PropParasites,
+        length: 0,
+        payload: PropertyPayload::Parasites(vec![ParasiteProperty {
+            name: "gimp-comment".to_string(),
+            flags: 1,
+            data: "Saved".to_string(),
+        }]),
+    });
+
+    let saved = round_trip(&image)?;
+    assert_eq!(
+        saved.header.properties[1].kind,
+        PropertyIdentifier::Unknown(1234)
+    );
+    assert_eq!(
+        saved.layers[1].properties[2].kind,
+        PropertyIdentifier::Unknown(999)
+    );
+    assert_eq!(
+        saved.layers[1].mask.as_ref().unwrap().native,
+        image.layers[1].mask.as_ref().unwrap().native
+    );
+    assert_eq!(
+        saved.channels[0].native.as_ref().unwrap().data,
+        (0..12).collect::<Vec<u8>>()
+    );
+    assert!(saved.layers[0].is_group());
+    assert_eq!(
+        saved.layer_by_path("Group/Sprite").map(|l| &l.name),
+        Some(&"Sprite".to_string())
+    );
+    assert_eq!(
+        saved.header.properties[2].payload,
+        image.header.properties[2].payload
+    );
+    Ok(())
+}
+
+#[test]
+fn save_edited_pixels() -> Result<(), Error> {
+    let mut image = Xcf::open("tests/samples/parsing/512x512-base-with-alpha.xcf")?;
+    let red = RgbaPixel::new(255, 0, 0, 255);
+    image.layers[0].pixels.pixels[0] = red;
+    let saved = round_trip(&image)?;
+    assert_eq!(saved.layers[0].pixel(0, 0), Some(red));
+    assert_eq!(saved.layers[0].pixels, image.layers[0].pixels);
+    // the other layers are written as they were
+    assert_eq!(saved.layers[1..], image.layers[1..]);
+
+    // a high precision channel is only scaled down once it is changed
+    let mut image = TestImage::new(2, 1);
+    image.precision = 250; // 16-bit gamma integer
+    image.channels = vec![TestChannel {
+        width: 2,
+        height: 1,
+        name: "Channel",
+        properties: vec![],
+        bpp: 2,
+        data: vec![0x12, 0x34, 0x56, 0x78],
+    }];
+    let mut image = Xcf::load(Cursor::new(image.to_bytes()))?;
+    assert_same(&image, &round_trip(&image)?);
+    image.channels[0].pixels[1] = 255;
+    let saved = round_trip(&image)?;
+    assert_eq!(saved.channels[0].pixels, image.channels[0].pixels);
+    assert_eq!(
+        saved.channels[0].native.as_ref().unwrap().data,
+        [0x12, 0x12, 0xff, 0xff]
+    );
+    Ok(())
+}
+
+#[test]
+fn save_pixels_of_the_wrong_size() -> Result<(), Error> {
+    let mut image = Xcf::open("tests/samples/parsing/512x512-base-with-alpha.xcf")?;
+    image.layers[0].width = 256;
+    assert!(matches!(round_trip(&image), Err(Error::InvalidFormat)));
+
+    let mut image = Xcf::open("tests/samples/parsing/512x512-base-with-alpha.xcf")?;
+    image.layers[0].native = None;
+    image.layers[0].pixels.pixels.pop();
+    assert!(matches!(round_trip(&image), Err(Error::InvalidFormat)));
+    Ok(())
+}
+
+#[test]
+fn save_floating_selection() -> Result<(), Error> {
+    let mut image = Xcf::open("tests/samples/parsing/512x512-base-with-alpha.xcf")?;
+    image.layers[0].properties.push(Property {
+        kind: PropertyIdentifier::PropFloatingSelection,
+        length: 4,
+        payload: PropertyPayload::FloatingSelection(1234),
+    });
+    assert!(matches!(round_trip(&image), Err(Error::NotSupported)));
+    Ok(())
+}
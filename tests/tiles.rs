@@ -1,5 +1,16 @@
+mod common;
+
+use std::io::{Cursor, Write};
+
+use common::{TestImage, TestLayer, group};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+use xcf_rs::data::error::Error;
 use xcf_rs::data::layer::Layer;
-use xcf_rs::data::tiles::Tiles;
+use xcf_rs::data::rect::Rect;
+use xcf_rs::data::tiles::{Tile, Tiles};
+use xcf_rs::data::xcf::{Xcf, XcfCompression};
 use xcf_rs::{LayerColorValue, LayerColorType};
 use xcf_rs::data::pixeldata::PixelData;
 
@@ -17,7 +28,9 @@ fn create_fake_layer(width: u32, height: u32) -> Layer {
             width: width,
             height: height,
             pixels: vec![]
-        }
+        },
+        native: None,
+        mask: None,
     }
 }
 
@@ -61,4 +74,175 @@ fn tiles_count_128x129() {
     let layer = create_fake_layer(128, 129);
     let tiles = Tiles::new(&layer);
     assert_eq!(tiles.nb, 6);
-}
\ No newline at end of file
+}
+/// Check that the tiles of a layer decode to its pixels.
+fn check_tiles(path: &str, name: &str, compression: XcfCompression) -> Result<usize, Error> {
+    let raw_image = Xcf::open(path)?;
+    let layer = raw_image.layer(name).unwrap();
+    let native = layer.native.as_ref().unwrap();
+    let lazy_image = Xcf::open_lazy(path)?;
+    let handle = lazy_image.layer(name).unwrap();
+
+    let mut count = 0;
+    for tile in lazy_image.layer_tiles(handle) {
+        let tile = tile?;
+        assert_eq!(tile.compression, compression);
+        assert_eq!(tile.bpp, native.bpp);
+        assert_eq!(
+            (tile.rect.x, tile.rect.y),
+            (tile.column as i32 * 64, tile.row as i32 * 64)
+        );
+        let pixels = tile.decode()?;
+        let row_length = (tile.rect.width * tile.bpp) as usize;
+        for (y, row) in pixels.chunks(row_length).enumerate() {
+            let x = tile.rect.x as u32;
+            let y = tile.rect.y as u32 + y as u32;
+            let start = ((y * native.width + x) * native.bpp) as usize;
+            assert_eq!(row, &native.data[start..start + row_length]);
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[test]
+fn iterate_tiles() -> Result<(), Error> {
+    let path = "tests/samples/parsing/246x6734-odd-size-odd-layer.xcf";
+    assert_eq!(check_tiles(path, "Layer 2", XcfCompression::Rle)?, 16);
+    assert_eq!(
+        check_tiles(path, "Background", XcfCompression::Rle)?,
+        4 * 106
+    );
+
+    let path = "tests/samples/parsing/1024x1024-better-compression.xcf";
+    let lazy_image = Xcf::open_lazy(path)?;
+    let handle = &lazy_image.layers[0];
+    let count = handle.width.div_ceil(64) * handle.height.div_ceil(64);
+    assert_eq!(
+        check_tiles(path, &handle.name, XcfCompression::Zlib)?,
+        count as usize
+    );
+
+    Ok(())
+}
+
+#[test]
+fn tiles_keep_their_bytes() -> Result<(), Error> {
+    let lazy_image = Xcf::open_lazy("tests/samples/parsing/246x6734-odd-size-odd-layer.xcf")?;
+    let handle = lazy_image.layer("Layer 2").unwrap();
+    let tiles = lazy_image
+        .layer_tiles(handle)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // the odd tiles on the edges
+    assert_eq!(tiles[3].rect, Rect::new(192, 0, 8, 64));
+    assert_eq!(tiles[15].rect, Rect::new(192, 192, 8, 8));
+    // the last tile has no next pointer to tell where it ends
+    let hierarchy = handle.hierarchy.as_ref().unwrap();
+    let pointers = &hierarchy.tile_pointers;
+    for (tile, pointer) in tiles.iter().zip(pointers.windows(2)) {
+        if pointer[1] != 0 {
+            assert_eq!(tile.data.len() as u64, pointer[1] - pointer[0]);
+        }
+    }
+    let last = &tiles[15];
+    assert!(!last.data.is_empty() && last.data.len() < 8 * 8 * 4 * 4);
+    assert_eq!(last.decode()?.len(), 8 * 8 * 4);
+
+    // groups have no tiles
+    let mut image = TestImage::new(1, 1);
+    image.layers = vec![TestLayer::rgba("Group", 1, 1, &[[0; 4]]).with(group())];
+    let lazy_image = Xcf::load_lazy(Cursor::new(image.to_bytes()))?;
+    assert_eq!(lazy_image.layer_tiles(&lazy_image.layers[0]).count(), 0);
+
+    // uncompressed tiles are the pixels
+    image.layers = vec![TestLayer::rgba("Sprite", 1, 1, &[[1, 2, 3, 4]])];
+    let lazy_image = Xcf::load_lazy(Cursor::new(image.to_bytes()))?;
+    let tile = lazy_image
+        .layer_tiles(&lazy_image.layers[0])
+        .next()
+        .unwrap()?;
+    assert_eq!(tile.compression, XcfCompression::None);
+    assert_eq!(tile.data, [1, 2, 3, 4]);
+
+    Ok(())
+}
+
+/// Decode tiles one after the other into the pixels of a layer or channel of `width` pixels.
+fn decode_in_sequence(
+    tiles: impl Iterator<Item = Result<Tile, Error>>,
+    width: u32,
+) -> Result<Vec<u8>, Error> {
+    let mut data = vec![];
+    for tile in tiles {
+        let tile = tile?;
+        let row_length = (tile.rect.width * tile.bpp) as usize;
+        for (y, row) in tile.decode()?.chunks(row_length).enumerate() {
+            let y = tile.rect.y as u32 + y as u32;
+            let start = ((y * width + tile.rect.x as u32) * tile.bpp) as usize;
+            if data.len() < start + row_length {
+                data.resize(start + row_length, 0);
+            }
+            data[start..start + row_length].copy_from_slice(row);
+        }
+    }
+    Ok(data)
+}
+
+#[test]
+fn decode_like_in_sequence() -> Result<(), Error> {
+    // with the rayon feature, the files are decoded in parallel
+    let samples = [
+        "tests/samples/parsing/246x6734-odd-size-odd-layer.xcf",
+        "tests/samples/parsing/1024x1024-better-compression.xcf",
+        "tests/samples/parsing/512x512-yellow-base-cloud-layer-empty-layer.xcf",
+    ];
+    for path in samples {
+        let raw_image = Xcf::open(path)?;
+        let lazy_image = Xcf::open_lazy(path)?;
+        for (layer, handle) in raw_image.layers.iter().zip(&lazy_image.layers) {
+            let data = decode_in_sequence(lazy_image.layer_tiles(handle), handle.width)?;
+            assert_eq!(layer.native.as_ref().unwrap().data, data, "{path}");
+        }
+        for (channel, handle) in raw_image.channels.iter().zip(&lazy_image.channels) {
+            let data = decode_in_sequence(lazy_image.channel_tiles(handle), handle.width)?;
+            assert_eq!(channel.native.as_ref().unwrap().data, data, "{path}");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn zlib_tiles_inflating_past_their_size() {
+    // a few kilobytes inflating to 16 MB, for a tile of 4 bytes
+    let mut encoder = ZlibEncoder::new(vec![], Compression::best());
+    encoder.write_all(&vec![0; 16 << 20]).unwrap();
+    let tile = Tile {
+        column: 0,
+        row: 0,
+        rect: Rect::new(0, 0, 1, 1),
+        compression: XcfCompression::Zlib,
+        bpp: 4,
+        data: encoder.finish().unwrap(),
+    };
+    assert!(tile.data.len() < 1 << 16);
+    assert!(matches!(tile.decode(), Err(Error::InvalidFormat)));
+}
+
+#[test]
+fn zlib_tile_pointer_out_of_the_file() {
+    let mut image = TestImage::new(65, 1);
+    image.compression = 2;
+    image
+        .layers
+        .push(TestLayer::rgba("Background", 65, 1, &[[1, 2, 3, 255]; 65]));
+    let mut data = image.to_bytes();
+    // the level follows the hierarchy, its tile pointers follow its size
+    let level = data
+        .windows(8)
+        .rposition(|w| w == [0, 0, 0, 65, 0, 0, 0, 1])
+        .unwrap();
+    let second_tile = level + 8 + 8;
+    data[second_tile..second_tile + 8].copy_from_slice(&(1u64 << 46).to_be_bytes());
+    assert!(Xcf::load(Cursor::new(data)).is_err());
+}